@@ -0,0 +1,4 @@
+-- Add Retry Bookkeeping to Issue Delivery Queue
+-- Tasks are only picked up once `next_attempt_at` is in the past.
+ALTER TABLE issue_delivery_queue ADD COLUMN n_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE issue_delivery_queue ADD COLUMN next_attempt_at timestamptz NOT NULL DEFAULT now();
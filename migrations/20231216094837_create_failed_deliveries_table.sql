@@ -0,0 +1,12 @@
+-- Create Failed Deliveries Table
+-- Deliveries that failed permanently or ran out of attempts,
+-- kept around until an admin puts them back in the queue.
+CREATE TABLE failed_deliveries (
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    n_attempts INTEGER NOT NULL,
+    last_error TEXT NOT NULL,
+    failed_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
@@ -0,0 +1,7 @@
+-- Add Expiry and Consumption Timestamps to Subscription Tokens
+-- Tokens issued before this migration get a fresh validity window.
+ALTER TABLE subscription_tokens ADD COLUMN created_at timestamptz NOT NULL DEFAULT now();
+ALTER TABLE subscription_tokens ADD COLUMN expires_at timestamptz NOT NULL DEFAULT now() + interval '1 day';
+ALTER TABLE subscription_tokens ALTER COLUMN expires_at DROP DEFAULT;
+-- Set once the token has been used to confirm a subscription.
+ALTER TABLE subscription_tokens ADD COLUMN consumed_at timestamptz NULL;
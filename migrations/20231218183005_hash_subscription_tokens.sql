@@ -0,0 +1,5 @@
+-- Store Subscription Tokens as SHA-256 Digests
+-- Existing tokens are rehashed in place: links already sent keep working.
+UPDATE subscription_tokens
+SET subscription_token = encode(sha256(convert_to(subscription_token, 'UTF8')), 'hex');
+ALTER TABLE subscription_tokens RENAME COLUMN subscription_token TO subscription_token_hash;
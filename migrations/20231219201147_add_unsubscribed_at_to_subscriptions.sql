@@ -0,0 +1,2 @@
+-- Subscribers leaving the list are kept, with status 'unsubscribed'
+ALTER TABLE subscriptions ADD COLUMN unsubscribed_at timestamptz NULL;
@@ -0,0 +1,10 @@
+-- Statuses are a closed set: let Postgres reject anything else
+CREATE TYPE subscription_status AS ENUM (
+    'pending_confirmation',
+    'confirmed',
+    'unsubscribed',
+    'bounced',
+    'complained'
+);
+ALTER TABLE subscriptions
+    ALTER COLUMN status TYPE subscription_status USING status::subscription_status;
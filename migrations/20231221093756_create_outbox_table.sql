@@ -0,0 +1,16 @@
+-- Emails written in the same transaction as the change they are about,
+-- delivered by the outbox dispatcher once committed.
+-- Rows are deleted as soon as they are delivered (or given up on): the
+-- confirmation links they carry are only stored in plain text until then.
+CREATE TABLE outbox(
+    id uuid NOT NULL,
+    recipient_email TEXT NOT NULL,
+    recipient_name TEXT NULL,
+    subject TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    n_attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (id)
+);
@@ -0,0 +1,3 @@
+-- The Markdown source of issues written in Markdown,
+-- their HTML and text content are derived from it.
+ALTER TABLE newsletter_issues ADD COLUMN markdown_content TEXT NULL;
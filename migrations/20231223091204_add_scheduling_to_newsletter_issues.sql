@@ -0,0 +1,16 @@
+-- Issues can be written now and sent later on, or cancelled before they are.
+CREATE TYPE newsletter_issue_status AS ENUM (
+    'scheduled',
+    'published',
+    'cancelled'
+);
+ALTER TABLE newsletter_issues
+    -- Issues published so far all went out right away
+    ADD COLUMN status newsletter_issue_status NOT NULL DEFAULT 'published',
+    ADD COLUMN send_at timestamptz NULL,
+    -- Only set once the delivery tasks are enqueued
+    ALTER COLUMN published_at DROP NOT NULL;
+ALTER TABLE newsletter_issues ALTER COLUMN status DROP DEFAULT;
+-- The scheduler looks for due issues on every run
+CREATE INDEX newsletter_issues_scheduled_send_at_idx
+    ON newsletter_issues (send_at) WHERE status = 'scheduled';
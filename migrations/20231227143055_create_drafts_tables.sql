@@ -0,0 +1,21 @@
+-- Issues being written: every save is kept as a revision.
+CREATE TABLE drafts (
+    draft_id uuid NOT NULL,
+    created_at timestamptz NOT NULL,
+    -- Set once published, the draft cannot be edited anymore
+    newsletter_issue_id uuid NULL
+        REFERENCES newsletter_issues (newsletter_issue_id),
+    PRIMARY KEY(draft_id)
+);
+CREATE TABLE draft_revisions (
+    draft_id uuid NOT NULL
+        REFERENCES drafts (draft_id) ON DELETE CASCADE,
+    -- Numbered from 1 within each draft
+    revision INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    markdown_content TEXT NOT NULL,
+    author_id uuid NOT NULL
+        REFERENCES users (user_id),
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY(draft_id, revision)
+);
@@ -0,0 +1,7 @@
+-- When the last subscription email (confirmation link or "already
+-- subscribed" note) was queued for the address, to throttle them.
+ALTER TABLE subscriptions ADD COLUMN last_emailed_at timestamptz NULL;
+UPDATE subscriptions
+SET last_emailed_at = (
+    SELECT MAX(created_at) FROM subscription_tokens WHERE subscriber_id = subscriptions.id
+);
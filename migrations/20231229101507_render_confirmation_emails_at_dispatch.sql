@@ -0,0 +1,16 @@
+-- Confirmation emails are rendered by the outbox dispatcher right before
+-- they are sent: the row only points at the subscriber, the token is issued
+-- (and only its hash stored) at that point, so raw confirmation links are
+-- never written to the database.
+ALTER TABLE outbox
+    ADD COLUMN confirmation_subscriber_id uuid NULL
+        REFERENCES subscriptions (id) ON DELETE CASCADE,
+    ALTER COLUMN html_content DROP NOT NULL,
+    ALTER COLUMN text_content DROP NOT NULL,
+    ADD CONSTRAINT outbox_content_or_confirmation CHECK (
+        (confirmation_subscriber_id IS NULL)
+        = (html_content IS NOT NULL AND text_content IS NOT NULL)
+    );
+
+-- Confirmation emails queued before this migration keep their rendered
+-- content: they go out as they are.
@@ -0,0 +1,91 @@
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::error::InternalError;
+use actix_web::{FromRequest, HttpMessage};
+use std::future::{ready, Future, Ready};
+use std::ops::Deref;
+use std::pin::Pin;
+use std::rc::Rc;
+use uuid::Uuid;
+
+use crate::{
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+// The id of the logged-in user, attached to the request by
+// `RejectAnonymousUsers` and retrieved in handlers with `web::ReqData<UserId>`.
+#[derive(Copy, Clone, Debug)]
+pub struct UserId(Uuid);
+
+impl std::fmt::Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Deref for UserId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Redirect requests without a logged-in user to the login page.
+pub struct RejectAnonymousUsers;
+
+impl<S, B> Transform<S, ServiceRequest> for RejectAnonymousUsers
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RejectAnonymousUsersMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RejectAnonymousUsersMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RejectAnonymousUsersMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RejectAnonymousUsersMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let session = {
+                let (http_request, payload) = req.parts_mut();
+                TypedSession::from_request(http_request, payload).await
+            }?;
+
+            match session.get_user_id().map_err(e500)? {
+                Some(user_id) => {
+                    req.extensions_mut().insert(UserId(user_id));
+                    service.call(req).await
+                }
+                None => {
+                    let e = anyhow::anyhow!("The user has not logged in.");
+                    Err(InternalError::from_response(e, see_other("/login")).into())
+                }
+            }
+        })
+    }
+}
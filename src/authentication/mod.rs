@@ -0,0 +1,5 @@
+mod middleware;
+mod password;
+
+pub use middleware::{RejectAnonymousUsers, UserId};
+pub use password::{change_password, create_user, validate_credentials, AuthError, Credentials};
@@ -1,13 +1,19 @@
+use anyhow::Context;
 use config::{Config, File};
 use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
 use serde_aux::prelude::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use sqlx::ConnectOptions;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use crate::domain::subscriber_email::SubscriberEmail;
+use crate::email_client::{
+    EmailTransport, FileTransport, MandrillTransport, PostmarkTransport, SmtpTlsMode, SmtpTransport,
+};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct Settings {
     database: DatabaseSettings,
     app: AppSettings,
@@ -47,12 +53,40 @@ impl Settings {
         self.email_client.base_url = url;
     }
 
+    // Relay emails through a plain text SMTP server, e.g. a local sink.
+    pub fn set_smtp_relay(&mut self, host: String, port: u16) {
+        self.email_client.provider = EmailProvider::Smtp;
+        self.email_client.smtp = Some(SmtpSettings {
+            host,
+            port,
+            tls: SmtpTlsMode::None,
+            username: None,
+            max_connections: default_smtp_max_connections(),
+        });
+    }
+
     pub fn get_app_base_url(&self) -> &str {
         &self.app.base_url
     }
+
+    pub fn get_hmac_secret(&self) -> &Secret<String> {
+        &self.app.hmac_secret
+    }
+
+    pub fn get_session_key(&self) -> &Secret<String> {
+        &self.app.session_key
+    }
+
+    pub fn get_subscription_token_ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.app.subscription_token_ttl_secs)
+    }
+
+    pub fn get_templates_dir(&self) -> &Path {
+        &self.app.templates_dir
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct DatabaseSettings {
     username: String,
     password: Secret<String>,
@@ -86,23 +120,118 @@ impl DatabaseSettings {
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct AppSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     port: u16,
     host: String,
     base_url: String,
+    hmac_secret: Secret<String>,
+    // Signs and encrypts the session cookies, see `startup::session_key`.
+    session_key: Secret<String>,
+    // How long a confirmation link stays valid.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    subscription_token_ttl_secs: u64,
+    // Where the email templates are read from, see `templates`.
+    templates_dir: PathBuf,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct EmailClientSettings {
+    #[serde(default)]
+    provider: EmailProvider,
     base_url: String,
     sender_email: String,
     secret: Secret<String>,
     timeout_ms: u64,
+    smtp: Option<SmtpSettings>,
+    // Only used by the `file` provider: emails go to stdout if unset.
+    output_dir: Option<String>,
+}
+
+// The backend used to deliver emails, see `email_client::EmailTransport`.
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailProvider {
+    #[default]
+    Postmark,
+    Mandrill,
+    Smtp,
+    File,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SmtpSettings {
+    host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    port: u16,
+    #[serde(default)]
+    tls: SmtpTlsMode,
+    // Authenticate with `email_client.secret` as password, if set.
+    username: Option<String>,
+    #[serde(default = "default_smtp_max_connections")]
+    max_connections: u32,
+}
+
+fn default_smtp_max_connections() -> u32 {
+    10
 }
 
 impl EmailClientSettings {
+    /// The backend for `provider`, failing if its settings are invalid.
+    pub fn transport(&self) -> Result<Arc<dyn EmailTransport>, anyhow::Error> {
+        let sender = self
+            .sender()
+            .map_err(|e| anyhow::anyhow!(e).context("Invalid sender email address."))?;
+
+        let transport: Arc<dyn EmailTransport> = match self.provider {
+            EmailProvider::Postmark => Arc::new(PostmarkTransport::new(
+                self.base_url.clone(),
+                sender,
+                self.get_secret(),
+                self.get_timeout(),
+            )),
+            EmailProvider::Mandrill => Arc::new(MandrillTransport::new(
+                self.base_url.clone(),
+                sender,
+                self.get_secret(),
+                self.get_timeout(),
+            )),
+            EmailProvider::Smtp => {
+                let smtp = self.smtp.as_ref().ok_or_else(|| {
+                    anyhow::anyhow!("The `smtp` provider requires `email_client.smtp` settings.")
+                })?;
+                let credentials = smtp
+                    .username
+                    .clone()
+                    .map(|username| (username, self.get_secret()));
+
+                Arc::new(
+                    SmtpTransport::new(
+                        &smtp.host,
+                        smtp.port,
+                        smtp.tls,
+                        credentials,
+                        smtp.max_connections,
+                        sender,
+                        self.get_timeout(),
+                    )
+                    .context("Invalid SMTP relay settings.")?,
+                )
+            }
+            EmailProvider::File => Arc::new(FileTransport::new(
+                sender,
+                self.output_dir.as_ref().map(PathBuf::from),
+            )),
+        };
+
+        Ok(transport)
+    }
+
+    pub fn get_provider(&self) -> EmailProvider {
+        self.provider
+    }
+
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
@@ -183,3 +312,34 @@ pub fn get_config() -> Result<Settings, config::ConfigError> {
 
     settings.try_deserialize::<Settings>()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email_client_settings(provider: EmailProvider, sender_email: &str) -> EmailClientSettings {
+        EmailClientSettings {
+            provider,
+            base_url: "http://127.0.0.1".into(),
+            sender_email: sender_email.into(),
+            secret: Secret::new("secret".into()),
+            timeout_ms: 1000,
+            smtp: None,
+            output_dir: None,
+        }
+    }
+
+    #[test]
+    fn an_invalid_sender_is_an_error_rather_than_a_panic() {
+        let settings = email_client_settings(EmailProvider::Postmark, "not-an-email");
+
+        assert!(settings.transport().is_err());
+    }
+
+    #[test]
+    fn the_smtp_provider_requires_smtp_settings() {
+        let settings = email_client_settings(EmailProvider::Smtp, "sender@example.com");
+
+        assert!(settings.transport().is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
+
+fn parser(markdown: &str) -> Parser<'_> {
+    Parser::new_ext(
+        markdown,
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH,
+    )
+}
+
+/// Render Markdown to HTML that is safe to send to subscribers.
+/// Markdown lets raw HTML through: the output goes through `sanitize_html`.
+pub fn to_html(markdown: &str) -> String {
+    // Link targets are percent-encoded, merge fields included: links to a
+    // merge field are written as raw HTML instead
+    let mut is_merge_field_link = Vec::new();
+    let events = parser(markdown).map(|event| match &event {
+        Event::Start(Tag::Link { dest_url, .. }) => {
+            let field = merge_field(dest_url);
+            is_merge_field_link.push(field.is_some());
+            match field {
+                Some(field) => Event::InlineHtml(format!(r#"<a href="{{{{ {field} }}}}">"#).into()),
+                None => event,
+            }
+        }
+        Event::End(TagEnd::Link) if is_merge_field_link.pop() == Some(true) => {
+            Event::InlineHtml("</a>".into())
+        }
+        _ => event,
+    });
+    let mut html_content = String::new();
+    html::push_html(&mut html_content, events);
+
+    sanitize_html(&html_content)
+}
+
+// The name of the field, if `s` is a single merge field, e.g. `{{ name }}`.
+fn merge_field(s: &str) -> Option<&str> {
+    let field = s.strip_prefix("{{")?.strip_suffix("}}")?.trim();
+    let is_identifier =
+        !field.is_empty() && field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    is_identifier.then_some(field)
+}
+
+/// Keep only the tags and attributes on the allow-list of `ammonia`:
+/// scripts, styles, event handlers and `javascript:` links are stripped.
+pub fn sanitize_html(html_content: &str) -> String {
+    ammonia::clean(html_content)
+}
+
+/// Render Markdown to plain text, for the text part of an email.
+/// Formatting is dropped, while the structure is kept readable: blank lines
+/// between blocks, `-` or numbers in front of list items and the target of
+/// links next to their text. Raw HTML is left out.
+pub fn to_text(markdown: &str) -> String {
+    let mut text = String::new();
+    // Ordered lists hold the number of their next item
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    // Target of each open link, and where its text starts
+    let mut links: Vec<(CowStr, usize)> = Vec::new();
+
+    for event in parser(markdown) {
+        match event {
+            Event::Start(Tag::List(first_number)) => {
+                if !text.is_empty() && !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                lists.push(first_number);
+            }
+            Event::End(TagEnd::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    text.push('\n');
+                }
+            }
+            Event::Start(Tag::Item) => {
+                text.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(number)) => {
+                        text.push_str(&format!("{number}. "));
+                        *number += 1;
+                    }
+                    _ => text.push_str("- "),
+                }
+            }
+            Event::End(TagEnd::Item) if !text.ends_with('\n') => text.push('\n'),
+            Event::Start(Tag::Link { dest_url, .. }) => links.push((dest_url, text.len())),
+            Event::End(TagEnd::Link) => {
+                if let Some((dest_url, start)) = links.pop() {
+                    // Autolinks already show their target
+                    if text[start..] != *dest_url {
+                        text.push_str(&format!(" ({dest_url})"));
+                    }
+                }
+            }
+            Event::End(TagEnd::Paragraph) if !lists.is_empty() => text.push('\n'),
+            Event::End(
+                TagEnd::Paragraph
+                | TagEnd::Heading(_)
+                | TagEnd::CodeBlock
+                | TagEnd::BlockQuote(_)
+                | TagEnd::Table,
+            ) => text.push_str("\n\n"),
+            Event::End(TagEnd::TableRow | TagEnd::TableHead) => text.push('\n'),
+            Event::End(TagEnd::TableCell) => text.push('\t'),
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak => text.push('\n'),
+            Event::Rule => text.push_str("---\n\n"),
+            _ => {}
+        }
+    }
+
+    text.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sanitize_html, to_html, to_text};
+
+    #[test]
+    fn markdown_is_rendered_to_html() {
+        let html_content = to_html("# Title\n\nSome *emphasis* and a [link](https://example.com).");
+
+        assert!(html_content.contains("<h1>Title</h1>"));
+        assert!(html_content.contains("<em>emphasis</em>"));
+        assert!(html_content.contains(r#"<a href="https://example.com""#));
+    }
+
+    #[test]
+    fn scripts_and_event_handlers_are_stripped() {
+        let html_content = to_html(
+            "Hello <script>alert(1)</script>\n\n<img src=\"x.png\" onerror=\"alert(1)\">\n\n\
+            [click](javascript:alert(1))",
+        );
+
+        assert!(!html_content.contains("<script"));
+        assert!(!html_content.contains("onerror"));
+        assert!(!html_content.contains("javascript:"));
+        assert!(html_content.contains(r#"<img src="x.png">"#));
+    }
+
+    #[test]
+    fn merge_fields_are_kept_as_link_targets() {
+        let html_content = to_html("[Unsubscribe](<{{ unsubscribe_url }}>), [home](/)");
+
+        assert!(html_content.contains(
+            r#"<a href="{{ unsubscribe_url }}" rel="noopener noreferrer">Unsubscribe</a>"#
+        ));
+        assert!(html_content.contains(r#"<a href="/" rel="noopener noreferrer">home</a>"#));
+    }
+
+    #[test]
+    fn html_content_is_sanitized_as_well() {
+        let html_content = sanitize_html(r#"<p onclick="alert(1)">Hi</p><style>p {}</style>"#);
+
+        assert_eq!(html_content, "<p>Hi</p>");
+    }
+
+    #[test]
+    fn markdown_is_rendered_to_readable_text() {
+        let text_content = to_text(
+            "# Title\n\nSome *emphasis* and a [link](https://example.com).\n\n\
+            - first\n- second\n\n1. one\n2. two\n\n<b>raw</b> html",
+        );
+
+        assert_eq!(
+            text_content,
+            "Title\n\n\
+            Some emphasis and a link (https://example.com).\n\n\
+            - first\n- second\n\n\
+            1. one\n2. two\n\n\
+            raw html"
+        );
+    }
+
+    #[test]
+    fn autolinks_are_not_repeated() {
+        assert_eq!(
+            to_text("See <https://example.com>"),
+            "See https://example.com"
+        );
+    }
+}
@@ -1,3 +1,10 @@
+pub mod markdown;
 pub mod new_subscriber;
+pub mod newsletter_issue;
+pub mod newsletter_issue_status;
+pub mod send_at;
 pub mod subscriber_email;
 pub mod subscriber_name;
+pub mod subscription_status;
+pub mod subscription_token;
+pub mod unsubscribe_token;
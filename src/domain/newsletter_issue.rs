@@ -0,0 +1,125 @@
+use super::markdown;
+use crate::templates::validate_merge_fields;
+
+#[derive(Debug)]
+pub struct NewsletterIssue {
+    title: String,
+    html_content: String,
+    text_content: String,
+    // The source of both bodies, if they were written in Markdown.
+    markdown_content: Option<String>,
+}
+
+impl NewsletterIssue {
+    /// The HTML body is sanitized, see `markdown::sanitize_html`.
+    /// Both bodies can only use the merge fields filled in at delivery time,
+    /// see `templates::MERGE_FIELDS`.
+    pub fn parse(
+        title: String,
+        html_content: String,
+        text_content: String,
+    ) -> Result<NewsletterIssue, String> {
+        if title.trim().is_empty() {
+            return Err("The newsletter title cannot be empty.".into());
+        }
+        if html_content.trim().is_empty() || text_content.trim().is_empty() {
+            return Err("The newsletter must have both an HTML and a plain text body.".into());
+        }
+        // Sanitizing escapes markup: check what will actually be rendered
+        let html_content = markdown::sanitize_html(&html_content);
+        validate_merge_fields(&html_content)?;
+        validate_merge_fields(&text_content)?;
+
+        Ok(Self {
+            title,
+            html_content,
+            text_content,
+            markdown_content: None,
+        })
+    }
+
+    /// Derive both bodies from a Markdown source.
+    pub fn from_markdown(title: String, markdown_content: String) -> Result<Self, String> {
+        let html_content = markdown::to_html(&markdown_content);
+        let text_content = markdown::to_text(&markdown_content);
+        if markdown_content.trim().is_empty() || text_content.trim().is_empty() {
+            return Err("The newsletter content cannot be empty.".into());
+        }
+
+        Ok(Self {
+            markdown_content: Some(markdown_content),
+            ..Self::parse(title, html_content, text_content)?
+        })
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn html_content(&self) -> &str {
+        &self.html_content
+    }
+
+    pub fn text_content(&self) -> &str {
+        &self.text_content
+    }
+
+    pub fn markdown_content(&self) -> Option<&str> {
+        self.markdown_content.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn a_complete_issue_is_parsed_successfully() {
+        let issue = NewsletterIssue::parse("Title".into(), "<p>Hi</p>".into(), "Hi".into());
+        assert_ok!(issue);
+    }
+
+    #[test]
+    fn a_whitespace_only_title_is_rejected() {
+        let issue = NewsletterIssue::parse(" ".into(), "<p>Hi</p>".into(), "Hi".into());
+        assert_err!(issue);
+    }
+
+    #[test]
+    fn an_issue_without_html_content_is_rejected() {
+        let issue = NewsletterIssue::parse("Title".into(), "".into(), "Hi".into());
+        assert_err!(issue);
+    }
+
+    #[test]
+    fn an_issue_without_text_content_is_rejected() {
+        let issue = NewsletterIssue::parse("Title".into(), "<p>Hi</p>".into(), "".into());
+        assert_err!(issue);
+    }
+
+    #[test]
+    fn an_issue_with_unknown_merge_fields_is_rejected() {
+        let issue = NewsletterIssue::parse(
+            "Title".into(),
+            "<p>Hi {{ name }}</p>".into(),
+            "Hi {{ nmae }}".into(),
+        );
+        assert_err!(issue);
+    }
+
+    #[test]
+    fn both_bodies_are_derived_from_markdown() {
+        let issue = NewsletterIssue::from_markdown("Title".into(), "Hi *there*".into()).unwrap();
+
+        assert_eq!(issue.html_content(), "<p>Hi <em>there</em></p>\n");
+        assert_eq!(issue.text_content(), "Hi there");
+        assert_eq!(issue.markdown_content(), Some("Hi *there*"));
+    }
+
+    #[test]
+    fn markdown_without_any_text_is_rejected() {
+        let issue = NewsletterIssue::from_markdown("Title".into(), "<script>x</script>".into());
+        assert_err!(issue);
+    }
+}
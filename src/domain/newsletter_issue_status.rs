@@ -0,0 +1,12 @@
+/// Where a newsletter issue stands, mapped to the `newsletter_issue_status`
+/// Postgres enum.
+/// Only `Scheduled` issues can be rescheduled or cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "newsletter_issue_status", rename_all = "snake_case")]
+pub enum NewsletterIssueStatus {
+    // Waiting for its `send_at`, see `issue_scheduler`.
+    Scheduled,
+    // Its deliveries have been enqueued.
+    Published,
+    Cancelled,
+}
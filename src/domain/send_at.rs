@@ -0,0 +1,62 @@
+use chrono::{DateTime, Utc};
+
+/// When a newsletter issue should go out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendAt(DateTime<Utc>);
+
+impl SendAt {
+    /// Expects an RFC 3339 timestamp: the offset is required, so that
+    /// `09:00` is not silently read in the timezone of the server.
+    pub fn parse(s: &str) -> Result<SendAt, String> {
+        DateTime::parse_from_rfc3339(s.trim())
+            .map(|send_at| Self(send_at.with_timezone(&Utc)))
+            .map_err(|_| {
+                format!(
+                    "{s} is not a valid send time. \
+                    Use an RFC 3339 timestamp, e.g. 2024-01-02T09:00:00+01:00."
+                )
+            })
+    }
+
+    pub fn is_in_the_future(&self) -> bool {
+        self.0 > Utc::now()
+    }
+}
+
+impl AsRef<DateTime<Utc>> for SendAt {
+    fn as_ref(&self) -> &DateTime<Utc> {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SendAt;
+    use chrono::{TimeZone, Utc};
+    use claim::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn timestamps_with_an_offset_are_converted_to_utc() {
+        let expected = SendAt(Utc.with_ymd_and_hms(2024, 1, 2, 8, 0, 0).unwrap());
+
+        assert_ok_eq!(SendAt::parse("2024-01-02T09:00:00+01:00"), expected);
+        assert_ok_eq!(SendAt::parse("2024-01-02T08:00:00Z"), expected);
+    }
+
+    #[test]
+    fn timestamps_without_an_offset_are_rejected() {
+        assert_err!(SendAt::parse("2024-01-02T09:00:00"));
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        assert_err!(SendAt::parse("next tuesday"));
+    }
+
+    #[test]
+    fn past_timestamps_are_not_in_the_future() {
+        assert!(!SendAt::parse("2000-01-01T00:00:00Z")
+            .unwrap()
+            .is_in_the_future());
+    }
+}
@@ -0,0 +1,129 @@
+/// Where a subscriber stands with the list, mapped to the
+/// `subscription_status` Postgres enum.
+/// Only `Confirmed` subscribers receive newsletter issues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "subscription_status", rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+    // The mailbox does not exist (anymore) or keeps rejecting our emails.
+    Bounced,
+    // The subscriber flagged one of our emails as spam.
+    Complained,
+}
+
+impl SubscriptionStatus {
+    /// The status to move to, if `next` can be reached from the current one.
+    /// - a confirmation link moves anyone not yet (or no longer) on the list
+    ///   to `Confirmed`: it proves both consent and a working mailbox;
+    /// - only `Confirmed` subscribers can unsubscribe;
+    /// - bounces and complaints can happen at any point.
+    ///
+    /// Leaving the list invalidates the confirmation links sent so far, see
+    /// `remove_from_list`: coming back takes a fresh one.
+    /// Staying in the same status is not a transition.
+    pub fn transition_to(self, next: Self) -> Result<Self, String> {
+        use SubscriptionStatus::*;
+
+        let is_allowed = match (self, next) {
+            (current, next) if current == next => false,
+            (PendingConfirmation | Unsubscribed, Confirmed) => true,
+            // A fresh link, requested by subscribing again, proves the
+            // mailbox works and the spam report is behind them
+            (Bounced | Complained, Confirmed) => true,
+            (Confirmed, Unsubscribed) => true,
+            (_, Bounced | Complained) => true,
+            _ => false,
+        };
+
+        if is_allowed {
+            Ok(next)
+        } else {
+            Err(format!(
+                "A subscription cannot go from `{}` to `{}`.",
+                self.as_str(),
+                next.as_str()
+            ))
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PendingConfirmation => "pending_confirmation",
+            Self::Confirmed => "confirmed",
+            Self::Unsubscribed => "unsubscribed",
+            Self::Bounced => "bounced",
+            Self::Complained => "complained",
+        }
+    }
+}
+
+impl std::fmt::Display for SubscriptionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriptionStatus::{self, *};
+    use claim::{assert_err, assert_ok_eq};
+
+    const ALL: [SubscriptionStatus; 5] = [
+        PendingConfirmation,
+        Confirmed,
+        Unsubscribed,
+        Bounced,
+        Complained,
+    ];
+
+    #[test]
+    fn pending_subscribers_can_confirm() {
+        assert_ok_eq!(PendingConfirmation.transition_to(Confirmed), Confirmed);
+    }
+
+    #[test]
+    fn unsubscribed_subscribers_can_confirm_again() {
+        assert_ok_eq!(Unsubscribed.transition_to(Confirmed), Confirmed);
+    }
+
+    #[test]
+    fn bounced_or_complaining_subscribers_can_confirm_again() {
+        assert_ok_eq!(Bounced.transition_to(Confirmed), Confirmed);
+        assert_ok_eq!(Complained.transition_to(Confirmed), Confirmed);
+    }
+
+    #[test]
+    fn only_confirmed_subscribers_can_unsubscribe() {
+        assert_ok_eq!(Confirmed.transition_to(Unsubscribed), Unsubscribed);
+        for status in [PendingConfirmation, Bounced, Complained] {
+            assert_err!(status.transition_to(Unsubscribed));
+        }
+    }
+
+    #[test]
+    fn any_subscriber_can_bounce_or_complain() {
+        for status in ALL {
+            for next in [Bounced, Complained] {
+                if status != next {
+                    assert_ok_eq!(status.transition_to(next), next);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nobody_goes_back_to_pending_confirmation() {
+        for status in ALL {
+            assert_err!(status.transition_to(PendingConfirmation));
+        }
+    }
+
+    #[test]
+    fn staying_in_the_same_status_is_rejected() {
+        for status in ALL {
+            assert_err!(status.transition_to(status));
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::{rngs::OsRng, RngCore};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+
+// 256 bits straight from the OS CSPRNG.
+const TOKEN_LENGTH_BYTES: usize = 32;
+
+/// The token embedded in a confirmation link.
+/// Only its digest is ever stored: a leaked database cannot be used
+/// to confirm (or unsubscribe) anyone.
+#[derive(Debug)]
+pub struct SubscriptionToken(Secret<String>);
+
+impl SubscriptionToken {
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; TOKEN_LENGTH_BYTES];
+        OsRng.fill_bytes(&mut bytes);
+
+        // URL-safe, it can go in a query string as is
+        Self(Secret::new(URL_SAFE_NO_PAD.encode(bytes)))
+    }
+
+    // Tokens coming back from a link are not validated: an unknown digest
+    // is rejected all the same when looked up.
+    pub fn from_link(token: String) -> Self {
+        Self(Secret::new(token))
+    }
+
+    /// Hex-encoded SHA-256 digest, as stored in `subscription_tokens`.
+    /// Tokens carry enough entropy to make a plain (unsalted) hash safe.
+    pub fn hash(&self) -> String {
+        hex::encode(Sha256::digest(self.0.expose_secret().as_bytes()))
+    }
+
+    pub fn expose(&self) -> &str {
+        self.0.expose_secret()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriptionToken;
+
+    #[test]
+    fn generated_tokens_are_url_safe() {
+        let token = SubscriptionToken::generate();
+
+        assert_eq!(token.expose().len(), 43);
+        assert!(token
+            .expose()
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+    }
+
+    #[test]
+    fn generated_tokens_are_unique() {
+        let first = SubscriptionToken::generate();
+        let second = SubscriptionToken::generate();
+
+        assert_ne!(first.expose(), second.expose());
+    }
+
+    #[test]
+    fn the_digest_is_a_hex_encoded_sha256() {
+        let token = SubscriptionToken::from_link("abc".into());
+
+        assert_eq!(
+            token.hash(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn the_digest_does_not_contain_the_token() {
+        let token = SubscriptionToken::generate();
+
+        assert!(!token.hash().contains(token.expose()));
+    }
+}
@@ -0,0 +1,110 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+// Keeps these signatures apart from anything else signed with the same secret.
+const SIGNATURE_CONTEXT: &[u8] = b"unsubscribe:";
+const ID_LENGTH_BYTES: usize = 16;
+
+/// The token embedded in an unsubscribe link: the id of the subscriber
+/// followed by its HMAC-SHA256 signature.
+/// Nothing is stored, so a link can be built for every delivery and keeps
+/// working for as long as the secret does not change.
+#[derive(Debug)]
+pub struct UnsubscribeToken(String);
+
+impl UnsubscribeToken {
+    pub fn sign(subscriber_id: Uuid, hmac_secret: &Secret<String>) -> Self {
+        let signature = signer(hmac_secret, subscriber_id).finalize().into_bytes();
+        let mut bytes = subscriber_id.as_bytes().to_vec();
+        bytes.extend_from_slice(&signature);
+
+        // URL-safe, it can go in a query string as is
+        Self(URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    /// Returns the id of the subscriber the token was signed for.
+    pub fn verify(token: &str, hmac_secret: &Secret<String>) -> Result<Uuid, String> {
+        let invalid = || "The unsubscribe token is invalid.".to_string();
+
+        let bytes = URL_SAFE_NO_PAD.decode(token).map_err(|_| invalid())?;
+        if bytes.len() <= ID_LENGTH_BYTES {
+            return Err(invalid());
+        }
+        let (id, signature) = bytes.split_at(ID_LENGTH_BYTES);
+        let subscriber_id = Uuid::from_slice(id).map_err(|_| invalid())?;
+
+        // Constant-time comparison
+        signer(hmac_secret, subscriber_id)
+            .verify_slice(signature)
+            .map_err(|_| invalid())?;
+
+        Ok(subscriber_id)
+    }
+}
+
+impl AsRef<str> for UnsubscribeToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+fn signer(hmac_secret: &Secret<String>, subscriber_id: Uuid) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes())
+        .expect("HMAC accepts keys of any length.");
+    mac.update(SIGNATURE_CONTEXT);
+    mac.update(subscriber_id.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnsubscribeToken;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use claim::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-very-long-and-secret-key".into())
+    }
+
+    #[test]
+    fn a_signed_token_resolves_to_its_subscriber() {
+        let subscriber_id = Uuid::new_v4();
+        let token = UnsubscribeToken::sign(subscriber_id, &secret());
+
+        assert_ok_eq!(
+            UnsubscribeToken::verify(token.as_ref(), &secret()),
+            subscriber_id
+        );
+    }
+
+    #[test]
+    fn tokens_signed_with_another_secret_are_rejected() {
+        let token = UnsubscribeToken::sign(Uuid::new_v4(), &Secret::new("another-key".into()));
+
+        assert_err!(UnsubscribeToken::verify(token.as_ref(), &secret()));
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let token = UnsubscribeToken::sign(Uuid::new_v4(), &secret());
+        // Swap the subscriber id, keep the signature
+        let mut tampered = Uuid::new_v4().as_bytes().to_vec();
+        tampered.extend_from_slice(&URL_SAFE_NO_PAD.decode(token.as_ref()).unwrap()[16..]);
+
+        assert_err!(UnsubscribeToken::verify(
+            &URL_SAFE_NO_PAD.encode(tampered),
+            &secret()
+        ));
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        assert_err!(UnsubscribeToken::verify("not-a-token", &secret()));
+        assert_err!(UnsubscribeToken::verify("", &secret()));
+    }
+}
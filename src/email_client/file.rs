@@ -0,0 +1,108 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+use super::{Email, EmailError, EmailTransport};
+use crate::domain::subscriber_email::SubscriberEmail;
+
+/// Local development backend: nothing leaves the machine.
+/// Each email is written to its own file in `output_dir`, or to stdout if unset.
+#[derive(Debug)]
+pub struct FileTransport {
+    sender: SubscriberEmail,
+    output_dir: Option<PathBuf>,
+}
+
+impl FileTransport {
+    pub fn new(sender: SubscriberEmail, output_dir: Option<PathBuf>) -> Self {
+        Self { sender, output_dir }
+    }
+}
+
+#[async_trait]
+impl EmailTransport for FileTransport {
+    fn sender(&self) -> &SubscriberEmail {
+        &self.sender
+    }
+
+    async fn send(&self, email: &Email<'_>) -> Result<(), EmailError> {
+        let rendered = render(email);
+
+        let outcome = match &self.output_dir {
+            Some(dir) => write_to_dir(dir, &rendered).await,
+            None => write_to_stdout(&rendered).await,
+        };
+
+        outcome.map_err(EmailError::Permanent)
+    }
+}
+
+async fn write_to_dir(dir: &Path, rendered: &str) -> Result<(), anyhow::Error> {
+    let path = dir.join(format!("{}.eml", uuid::Uuid::new_v4()));
+    tokio::fs::create_dir_all(dir)
+        .await
+        .with_context(|| format!("Failed to create {}.", dir.display()))?;
+    tokio::fs::write(&path, rendered)
+        .await
+        .with_context(|| format!("Failed to write the email to {}.", path.display()))
+}
+
+async fn write_to_stdout(rendered: &str) -> Result<(), anyhow::Error> {
+    let mut stdout = tokio::io::stdout();
+    stdout
+        .write_all(rendered.as_bytes())
+        .await
+        .context("Failed to write the email to stdout.")?;
+    stdout
+        .flush()
+        .await
+        .context("Failed to write the email to stdout.")
+}
+
+fn render(email: &Email<'_>) -> String {
+    let extra_headers: String = email
+        .list_unsubscribe_headers()
+        .into_iter()
+        .map(|(name, value)| format!("{name}: {value}\n"))
+        .collect();
+
+    format!(
+        "From: {}\nTo: {}\nSubject: {}\n{extra_headers}\n{}\n\n--- HTML ---\n{}\n\n",
+        email.from.as_ref(),
+        email.to.as_ref(),
+        email.subject,
+        email.text_content,
+        email.html_content
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::subscriber_email::SubscriberEmail;
+    use crate::email_client::{EmailTransport, FileTransport};
+    use claim::assert_ok;
+
+    #[actix_web::test]
+    async fn emails_are_written_to_the_output_directory() {
+        let output_dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let sender = SubscriberEmail::parse("newsletter@example.com".into()).unwrap();
+        let recipient = SubscriberEmail::parse("ursula_le_guin@example.com".into()).unwrap();
+        let transport = FileTransport::new(sender, Some(output_dir.clone()));
+
+        let outcome = transport
+            .send_email(&recipient, "Welcome!", "<p>Hello</p>", "Hello")
+            .await;
+        assert_ok!(outcome);
+
+        let files: Vec<_> = std::fs::read_dir(&output_dir).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        let content = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        assert!(content.contains("From: newsletter@example.com"));
+        assert!(content.contains("To: ursula_le_guin@example.com"));
+        assert!(content.contains("Subject: Welcome!"));
+        assert!(content.contains("<p>Hello</p>"));
+
+        std::fs::remove_dir_all(output_dir).unwrap();
+    }
+}
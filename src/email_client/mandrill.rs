@@ -0,0 +1,255 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use std::collections::BTreeMap;
+
+use super::{Email, EmailError, EmailTransport};
+use crate::domain::subscriber_email::SubscriberEmail;
+
+/// Mailchimp Transactional (formerly Mandrill) API.
+/// The API key travels in the body and per-recipient outcomes come back as a
+/// `200 OK`, so a rejected recipient has to be spotted in the response.
+#[derive(Debug)]
+pub struct MandrillTransport {
+    http_client: Client,
+    base_url: String,
+    sender: SubscriberEmail,
+    secret: Secret<String>,
+}
+
+impl MandrillTransport {
+    pub fn new(
+        base_url: String,
+        sender: SubscriberEmail,
+        secret: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        Self {
+            http_client: Client::builder().timeout(timeout).build().unwrap(),
+            base_url,
+            sender,
+            secret,
+        }
+    }
+}
+
+#[async_trait]
+impl EmailTransport for MandrillTransport {
+    fn sender(&self) -> &SubscriberEmail {
+        &self.sender
+    }
+
+    async fn send(&self, email: &Email<'_>) -> Result<(), EmailError> {
+        let url = format!("{}/messages/send.json", self.base_url);
+        let headers = email.list_unsubscribe_headers();
+        let request_body = SendEmailRequest {
+            key: self.secret.expose_secret(),
+            message: Message {
+                from_email: email.from.as_ref(),
+                to: vec![Recipient {
+                    email: email.to.as_ref(),
+                    name: email.to_name,
+                    r#type: "to",
+                }],
+                subject: email.subject,
+                html: email.html_content,
+                text: email.text_content,
+                headers: headers
+                    .iter()
+                    .map(|(name, value)| (*name, value.as_str()))
+                    .collect(),
+            },
+        };
+        let response = self
+            .http_client
+            .post(url)
+            .json(&request_body)
+            .send()
+            .await?
+            .error_for_status()?;
+        // The email has been accepted at this point: failing the delivery
+        // would have it sent twice on retry
+        let statuses: Vec<RecipientStatus> = match response.json().await {
+            Ok(statuses) => statuses,
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    "Failed to decode the response of Mandrill, assuming the email was sent."
+                );
+                return Ok(());
+            }
+        };
+
+        match statuses.iter().find(|s| s.is_rejected()) {
+            Some(rejected) => Err(EmailError::Permanent(anyhow::anyhow!(
+                "The recipient was {}: {}",
+                rejected.status,
+                rejected
+                    .reject_reason
+                    .as_deref()
+                    .unwrap_or("no reason given")
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct SendEmailRequest<'a> {
+    key: &'a str,
+    message: Message<'a>,
+}
+
+#[derive(serde::Serialize)]
+struct Message<'a> {
+    from_email: &'a str,
+    to: Vec<Recipient<'a>>,
+    subject: &'a str,
+    html: &'a str,
+    text: &'a str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<&'a str, &'a str>,
+}
+
+#[derive(serde::Serialize)]
+struct Recipient<'a> {
+    email: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    r#type: &'a str,
+}
+
+#[derive(serde::Deserialize)]
+struct RecipientStatus {
+    status: String,
+    reject_reason: Option<String>,
+}
+
+impl RecipientStatus {
+    fn is_rejected(&self) -> bool {
+        self.status == "rejected" || self.status == "invalid"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::subscriber_email::SubscriberEmail;
+    use crate::email_client::{EmailTransport, MandrillTransport};
+    use claim::{assert_err, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use secrecy::{ExposeSecret, Secret};
+    use wiremock::matchers::{any, body_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn mock_setup() -> (MockServer, MandrillTransport, Secret<String>) {
+        let mock_server = MockServer::start().await;
+        let sender = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+        let secret = Secret::new(Faker.fake::<String>());
+        let transport = MandrillTransport::new(
+            mock_server.uri(),
+            sender,
+            secret.clone(),
+            std::time::Duration::from_millis(200),
+        );
+
+        (mock_server, transport, secret)
+    }
+
+    fn recipient() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn sent(recipient: &SubscriberEmail) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            { "email": recipient.as_ref(), "status": "sent", "reject_reason": null }
+        ]))
+    }
+
+    #[actix_web::test]
+    async fn send_email_sends_the_expected_request() {
+        let (mock_server, transport, secret) = mock_setup().await;
+        let recipient = recipient();
+        let subject: String = Sentence(1..2).fake();
+        let html_content: String = Paragraph(1..10).fake();
+        let text_content: String = Paragraph(1..10).fake();
+
+        Mock::given(path("/messages/send.json"))
+            .and(method("POST"))
+            .and(body_json(serde_json::json!({
+                "key": secret.expose_secret(),
+                "message": {
+                    "from_email": transport.sender().as_ref(),
+                    "to": [{ "email": recipient.as_ref(), "type": "to" }],
+                    "subject": subject,
+                    "html": html_content,
+                    "text": text_content,
+                }
+            })))
+            .respond_with(sent(&recipient))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = transport
+            .send_email(&recipient, &subject, &html_content, &text_content)
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[actix_web::test]
+    async fn send_email_fails_permanently_if_the_recipient_is_rejected() {
+        let (mock_server, transport, _) = mock_setup().await;
+        let recipient = recipient();
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "email": recipient.as_ref(), "status": "rejected", "reject_reason": "hard-bounce" }
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = transport
+            .send_email(&recipient, "Subject", "<p>HTML</p>", "Text")
+            .await;
+
+        assert!(!assert_err!(outcome).is_transient());
+    }
+
+    #[actix_web::test]
+    async fn send_email_succeeds_if_an_accepted_email_gets_an_unexpected_response() {
+        let (mock_server, transport, _) = mock_setup().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_string("not json"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = transport
+            .send_email(&recipient(), "Subject", "<p>HTML</p>", "Text")
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[actix_web::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        let (mock_server, transport, _) = mock_setup().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = transport
+            .send_email(&recipient(), "Subject", "<p>HTML</p>", "Text")
+            .await;
+
+        assert!(assert_err!(outcome).is_transient());
+    }
+}
@@ -0,0 +1,128 @@
+mod file;
+mod mandrill;
+mod postmark;
+mod smtp;
+
+pub use file::FileTransport;
+pub use mandrill::MandrillTransport;
+pub use postmark::PostmarkTransport;
+pub use smtp::{SmtpTlsMode, SmtpTransport};
+
+use async_trait::async_trait;
+use lettre::address::AddressError;
+use lettre::message::Mailbox;
+use reqwest::StatusCode;
+
+use crate::{domain::subscriber_email::SubscriberEmail, services::subscriptions::error_chain_fmt};
+
+/// A fully-built message, ready to be handed over to a transport.
+#[derive(Debug)]
+pub struct Email<'a> {
+    pub from: &'a SubscriberEmail,
+    pub to: &'a SubscriberEmail,
+    // Display name of the recipient, if known: it may contain any character.
+    pub to_name: Option<&'a str>,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    // Where recipients can unsubscribe in one click (RFC 8058), for emails
+    // sent to the whole list.
+    pub unsubscribe_url: Option<&'a str>,
+}
+
+/// Something able to deliver emails: a provider API, an SMTP relay, a file...
+/// Background workers get an `Arc<dyn EmailTransport>` and never know which one:
+/// request handlers only ever write to the outbox.
+#[async_trait]
+pub trait EmailTransport: Send + Sync {
+    /// The address every email is sent from.
+    fn sender(&self) -> &SubscriberEmail;
+
+    async fn send(&self, email: &Email<'_>) -> Result<(), EmailError>;
+
+    async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), EmailError> {
+        let email = Email {
+            from: self.sender(),
+            to: recipient,
+            to_name: None,
+            subject,
+            html_content,
+            text_content,
+            unsubscribe_url: None,
+        };
+
+        self.send(&email).await
+    }
+}
+
+impl Email<'_> {
+    /// The recipient, with its display name if known.
+    /// Its `Display` output is suitable for JSON APIs; SMTP headers
+    /// encode non-ASCII names when the message is built.
+    pub fn to_mailbox(&self) -> Result<Mailbox, AddressError> {
+        Ok(Mailbox::new(
+            self.to_name.map(ToOwned::to_owned),
+            self.to.as_ref().parse()?,
+        ))
+    }
+
+    /// `List-Unsubscribe` and `List-Unsubscribe-Post` headers, if the email
+    /// has an unsubscribe URL: mail clients then offer a one-click button.
+    pub fn list_unsubscribe_headers(&self) -> Vec<(&'static str, String)> {
+        match self.unsubscribe_url {
+            Some(url) => vec![
+                ("List-Unsubscribe", format!("<{url}>")),
+                (
+                    "List-Unsubscribe-Post",
+                    "List-Unsubscribe=One-Click".to_string(),
+                ),
+            ],
+            None => Vec::new(),
+        }
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum EmailError {
+    // Worth retrying later: the provider is down, overloaded or unreachable.
+    #[error("The email provider could not be reached or is unavailable.")]
+    Transient(#[source] anyhow::Error),
+    // Sending the same email again will fail the same way.
+    #[error("The email provider rejected the email.")]
+    Permanent(#[source] anyhow::Error),
+}
+
+impl EmailError {
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Transient(_))
+    }
+}
+
+impl std::fmt::Debug for EmailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl From<reqwest::Error> for EmailError {
+    fn from(e: reqwest::Error) -> Self {
+        // Server errors, rate limiting and network hiccups are worth retrying:
+        // any other 4xx will be rejected again with the same payload.
+        let is_transient = match e.status() {
+            Some(status) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+            None => e.is_timeout() || e.is_connect() || e.is_request(),
+        };
+
+        if is_transient {
+            Self::Transient(e.into())
+        } else {
+            Self::Permanent(e.into())
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+
+use super::{Email, EmailError, EmailTransport};
+use crate::domain::subscriber_email::SubscriberEmail;
+
+/// Postmark-style JSON API: one POST to `/email` per message,
+/// authenticated with a server token header.
+#[derive(Debug)]
+pub struct PostmarkTransport {
+    http_client: Client,
+    base_url: String,
+    sender: SubscriberEmail,
+    secret: Secret<String>,
+}
+
+impl PostmarkTransport {
+    pub fn new(
+        base_url: String,
+        sender: SubscriberEmail,
+        secret: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        Self {
+            http_client: Client::builder().timeout(timeout).build().unwrap(),
+            base_url,
+            sender,
+            secret,
+        }
+    }
+}
+
+#[async_trait]
+impl EmailTransport for PostmarkTransport {
+    fn sender(&self) -> &SubscriberEmail {
+        &self.sender
+    }
+
+    async fn send(&self, email: &Email<'_>) -> Result<(), EmailError> {
+        let url = format!("{}/email", self.base_url);
+        let to = email
+            .to_mailbox()
+            .map_err(|e| EmailError::Permanent(e.into()))?
+            .to_string();
+        let headers = email.list_unsubscribe_headers();
+        let request_body = SendEmailRequest {
+            from: email.from.as_ref(),
+            to: &to,
+            subject: email.subject,
+            html_body: email.html_content,
+            text_body: email.text_content,
+            headers: headers
+                .iter()
+                .map(|(name, value)| Header { name, value })
+                .collect(),
+        };
+        self.http_client
+            .post(url)
+            .header("X-Postmark-Server-Token", self.secret.expose_secret())
+            .json(&request_body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<Header<'a>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Header<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::subscriber_email::SubscriberEmail;
+    use crate::email_client::{EmailError, EmailTransport, PostmarkTransport};
+    use claim::{assert_err, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use secrecy::{ExposeSecret, Secret};
+    use wiremock::matchers::{any, header, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    struct SendEmailBodyMatcher {
+        expected: serde_json::Value,
+    }
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            // Try to parse the body as a JSON value
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                // Every field must be there, with the expected value, and nothing else
+                body == self.expected
+            } else {
+                // If parsing failed, do not match the request
+                false
+            }
+        }
+    }
+
+    struct Email {
+        recipient: SubscriberEmail,
+        subject: String,
+        html_content: String,
+        text_content: String,
+    }
+
+    fn email() -> Email {
+        Email {
+            recipient: SubscriberEmail::parse(SafeEmail().fake()).unwrap(),
+            subject: Sentence(1..2).fake(),
+            html_content: Paragraph(1..10).fake(),
+            text_content: Paragraph(1..10).fake(),
+        }
+    }
+
+    async fn send(email_client: &PostmarkTransport, email: &Email) -> Result<(), EmailError> {
+        email_client
+            .send_email(
+                &email.recipient,
+                &email.subject,
+                &email.html_content,
+                &email.text_content,
+            )
+            .await
+    }
+
+    async fn mock_setup() -> (MockServer, PostmarkTransport, Secret<String>) {
+        let mock_server = MockServer::start().await;
+        let sender = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+        let secret = Secret::new(Faker.fake::<String>());
+        let email_client = PostmarkTransport::new(
+            mock_server.uri(),
+            sender,
+            secret.clone(),
+            std::time::Duration::from_millis(200),
+        );
+
+        (mock_server, email_client, secret)
+    }
+
+    #[actix_web::test]
+    async fn send_email_sends_the_expected_request() {
+        let (mock_server, email_client, secret) = mock_setup().await;
+        let email = email();
+
+        Mock::given(header(
+            "X-Postmark-Server-Token",
+            secret.expose_secret().as_str(),
+        ))
+        .and(header("Content-Type", "application/json"))
+        .and(path("/email"))
+        .and(method("POST"))
+        .and(SendEmailBodyMatcher {
+            expected: serde_json::json!({
+                "From": email_client.sender.as_ref(),
+                "To": email.recipient.as_ref(),
+                "Subject": email.subject,
+                "HtmlBody": email.html_content,
+                "TextBody": email.text_content,
+            }),
+        })
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+        let _ = send(&email_client, &email).await;
+    }
+
+    #[actix_web::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        let (mock_server, email_client, _) = mock_setup().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = send(&email_client, &email()).await;
+
+        assert_ok!(outcome);
+    }
+
+    #[actix_web::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        let (mock_server, email_client, _) = mock_setup().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = send(&email_client, &email()).await;
+
+        assert!(assert_err!(outcome).is_transient());
+    }
+
+    #[actix_web::test]
+    async fn send_email_fails_permanently_if_the_server_returns_422() {
+        let (mock_server, email_client, _) = mock_setup().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = send(&email_client, &email()).await;
+
+        assert!(!assert_err!(outcome).is_transient());
+    }
+
+    #[actix_web::test]
+    async fn send_email_times_out_if_server_takes_too_long() {
+        let (mock_server, email_client, _) = mock_setup().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = send(&email_client, &email()).await;
+
+        assert!(assert_err!(outcome).is_transient());
+    }
+}
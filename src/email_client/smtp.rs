@@ -0,0 +1,188 @@
+use async_trait::async_trait;
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::PoolConfig;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use secrecy::{ExposeSecret, Secret};
+
+use super::{Email, EmailError, EmailTransport};
+use crate::domain::subscriber_email::SubscriberEmail;
+
+/// How the connection to the SMTP relay is secured.
+#[derive(serde::Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTlsMode {
+    // Plain text: only for relays on a trusted network (or tests).
+    None,
+    // Connect in plain text, then upgrade with `STARTTLS` (usually port 587).
+    #[default]
+    StartTls,
+    // TLS from the first byte (usually port 465).
+    Tls,
+}
+
+/// Relay emails through an SMTP server.
+/// Connections are pooled and reused across emails.
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+    sender: SubscriberEmail,
+}
+
+impl SmtpTransport {
+    pub fn new(
+        host: &str,
+        port: u16,
+        tls_mode: SmtpTlsMode,
+        credentials: Option<(String, Secret<String>)>,
+        max_connections: u32,
+        sender: SubscriberEmail,
+        timeout: std::time::Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let builder = match tls_mode {
+            SmtpTlsMode::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            SmtpTlsMode::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpTlsMode::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+        };
+        let mut builder = builder
+            .port(port)
+            .timeout(Some(timeout))
+            .pool_config(PoolConfig::new().max_size(max_connections));
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(
+                username,
+                password.expose_secret().to_owned(),
+            ));
+        }
+
+        Ok(Self {
+            mailer: builder.build(),
+            sender,
+        })
+    }
+}
+
+#[async_trait]
+impl EmailTransport for SmtpTransport {
+    fn sender(&self) -> &SubscriberEmail {
+        &self.sender
+    }
+
+    async fn send(&self, email: &Email<'_>) -> Result<(), EmailError> {
+        let message = build_message(email).map_err(EmailError::Permanent)?;
+
+        self.mailer.send(message).await.map_err(|e| {
+            // 4xx replies, timeouts and connection failures can be retried,
+            // a 5xx reply will be given again for the same message.
+            if e.is_permanent() {
+                EmailError::Permanent(e.into())
+            } else {
+                EmailError::Transient(e.into())
+            }
+        })?;
+
+        Ok(())
+    }
+}
+
+// A multipart/alternative message: clients pick the HTML part if they can
+// render it, the plain text one otherwise.
+// Non-ASCII display names and subjects are encoded by `lettre` (RFC 2047).
+fn build_message(email: &Email<'_>) -> Result<Message, anyhow::Error> {
+    let from: Mailbox = email.from.as_ref().parse()?;
+
+    let mut builder = Message::builder()
+        .from(from)
+        .to(email.to_mailbox()?)
+        .subject(email.subject);
+    for (name, value) in email.list_unsubscribe_headers() {
+        builder = builder.raw_header(HeaderValue::new(
+            HeaderName::new_from_ascii_str(name),
+            value,
+        ));
+    }
+    let message = builder.multipart(MultiPart::alternative_plain_html(
+        email.text_content.to_owned(),
+        email.html_content.to_owned(),
+    ))?;
+
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build_message;
+    use crate::domain::subscriber_email::SubscriberEmail;
+    use crate::email_client::Email;
+
+    fn formatted(email: &Email<'_>) -> String {
+        String::from_utf8(build_message(email).unwrap().formatted()).unwrap()
+    }
+
+    #[test]
+    fn messages_have_a_text_and_an_html_alternative() {
+        let from = SubscriberEmail::parse("newsletter@example.com".into()).unwrap();
+        let to = SubscriberEmail::parse("ursula_le_guin@example.com".into()).unwrap();
+        let message = formatted(&Email {
+            from: &from,
+            to: &to,
+            to_name: None,
+            subject: "Welcome!",
+            html_content: "<p>Hello</p>",
+            text_content: "Hello",
+            unsubscribe_url: None,
+        });
+
+        assert!(message.contains("Content-Type: multipart/alternative;"));
+        assert!(message.contains("Content-Type: text/plain; charset=utf-8"));
+        assert!(message.contains("Content-Type: text/html; charset=utf-8"));
+        assert!(message.contains("<p>Hello</p>"));
+        assert!(message.contains("To: ursula_le_guin@example.com"));
+        assert!(message.contains("Subject: Welcome!"));
+    }
+
+    #[test]
+    fn non_ascii_names_and_subjects_are_encoded() {
+        let from = SubscriberEmail::parse("newsletter@example.com".into()).unwrap();
+        let to = SubscriberEmail::parse("zoe@example.com".into()).unwrap();
+        let message = formatted(&Email {
+            from: &from,
+            to: &to,
+            to_name: Some("Zoë Ångström"),
+            subject: "Bienvenue à bord",
+            html_content: "<p>Hello</p>",
+            text_content: "Hello",
+            unsubscribe_url: None,
+        });
+
+        let headers = message.split("\r\n\r\n").next().unwrap();
+        assert!(headers.is_ascii());
+        assert!(headers.contains("To: =?utf-8?b?"));
+        assert!(headers.contains("<zoe@example.com>"));
+        let subject = headers
+            .lines()
+            .find(|l| l.starts_with("Subject: "))
+            .unwrap();
+        assert!(subject.contains("=?utf-8?b?"));
+    }
+
+    #[test]
+    fn list_emails_can_be_unsubscribed_in_one_click() {
+        let from = SubscriberEmail::parse("newsletter@example.com".into()).unwrap();
+        let to = SubscriberEmail::parse("ursula_le_guin@example.com".into()).unwrap();
+        let message = formatted(&Email {
+            from: &from,
+            to: &to,
+            to_name: None,
+            subject: "Issue #1",
+            html_content: "<p>Hello</p>",
+            text_content: "Hello",
+            unsubscribe_url: Some("https://example.com/subscriptions/unsubscribe?token=abc"),
+        });
+
+        assert!(message.contains(
+            "List-Unsubscribe: <https://example.com/subscriptions/unsubscribe?token=abc>"
+        ));
+        assert!(message.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+    }
+}
@@ -0,0 +1,384 @@
+use chrono::Utc;
+use rand::Rng;
+use secrecy::Secret;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+use crate::{
+    config::Settings,
+    domain::{
+        subscriber_email::SubscriberEmail, subscription_status::SubscriptionStatus,
+        unsubscribe_token::UnsubscribeToken,
+    },
+    email_client::{Email, EmailError, EmailTransport},
+    startup::get_connection_pool,
+    templates::{EmailTemplates, MergeFields},
+};
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+// How long to wait before polling again when there is nothing to deliver.
+const EMPTY_QUEUE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+// Bounds of the back-off applied after consecutive failures.
+pub const MIN_ERROR_BACKOFF: Duration = Duration::from_secs(1);
+pub const MAX_ERROR_BACKOFF: Duration = Duration::from_secs(60);
+// Deliveries still failing after this many attempts are given up on.
+pub const MAX_DELIVERY_ATTEMPTS: i32 = 5;
+// Bounds of the delay between two attempts of the same delivery.
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(30);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+/// Process deliveries until the process is stopped.
+/// Can run alongside the API or on its own, see `--worker-only` in `main`.
+pub async fn run_worker_until_stopped(config: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&config);
+    let email_client = config.get_email_client().transport()?;
+    let templates = EmailTemplates::load(config.get_templates_dir())?;
+
+    worker_loop(
+        connection_pool,
+        email_client,
+        &templates,
+        config.get_app_base_url(),
+        config.get_hmac_secret(),
+    )
+    .await
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: Arc<dyn EmailTransport>,
+    templates: &EmailTemplates,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+) -> Result<(), anyhow::Error> {
+    let mut error_backoff = MIN_ERROR_BACKOFF;
+
+    loop {
+        match try_execute_task(
+            &pool,
+            email_client.as_ref(),
+            templates,
+            base_url,
+            hmac_secret,
+        )
+        .await
+        {
+            Ok(ExecutionOutcome::TaskCompleted) => {
+                error_backoff = MIN_ERROR_BACKOFF;
+            }
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                error_backoff = MIN_ERROR_BACKOFF;
+                tokio::time::sleep(EMPTY_QUEUE_POLL_INTERVAL).await;
+            }
+            Err(_) => {
+                // The error has already been logged by `try_execute_task`:
+                // give the database (or the network) some room before retrying.
+                tokio::time::sleep(error_backoff).await;
+                error_backoff = (error_backoff * 2).min(MAX_ERROR_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Pick a single due delivery from the queue and try to send it.
+/// On success the task is removed; transient failures are rescheduled with
+/// an exponential back-off, everything else ends up in `failed_deliveries`.
+/// Safe to call from several workers at once: the task row stays locked
+/// until the transaction commits, and locked rows are skipped by other workers.
+/// Every issue is personalised for its recipient, wrapped in the
+/// `newsletter_issue` template and carries a signed link to unsubscribe,
+/// rooted at `base_url`.
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id=tracing::field::Empty,
+        subscriber_email=tracing::field::Empty,
+        n_attempts=tracing::field::Empty
+    ),
+    err
+)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &dyn EmailTransport,
+    templates: &EmailTemplates,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((transaction, task)) = dequeue_task(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current()
+        .record("newsletter_issue_id", display(task.newsletter_issue_id))
+        .record("subscriber_email", display(&task.subscriber_email))
+        .record("n_attempts", task.n_attempts);
+
+    let Some(subscriber) = get_confirmed_subscriber(pool, &task.subscriber_email).await? else {
+        // Unsubscribed since the issue was published: nothing to deliver
+        delete_task(transaction, &task).await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    };
+    let issue = get_issue(pool, task.newsletter_issue_id).await?;
+    let unsubscribe_url = format!(
+        "{base_url}/subscriptions/unsubscribe?token={}",
+        UnsubscribeToken::sign(subscriber.id, hmac_secret).as_ref()
+    );
+    let fields = MergeFields {
+        name: &subscriber.name,
+        email: &task.subscriber_email,
+        unsubscribe_url: &unsubscribe_url,
+    };
+    let outcome = async {
+        let recipient = SubscriberEmail::parse(task.subscriber_email.clone()).map_err(|e| {
+            EmailError::Permanent(
+                anyhow::anyhow!(e)
+                    .context("The stored contact details of the subscriber are invalid."),
+            )
+        })?;
+        let content = templates
+            .newsletter_issue(&issue.html_content, &issue.text_content, &fields)
+            .map_err(|e| {
+                EmailError::Permanent(
+                    anyhow::Error::new(e).context("Failed to render the newsletter issue."),
+                )
+            })?;
+        let email = Email {
+            from: email_client.sender(),
+            to: &recipient,
+            to_name: Some(&subscriber.name),
+            subject: &issue.title,
+            html_content: &content.html_content,
+            text_content: &content.text_content,
+            unsubscribe_url: Some(&unsubscribe_url),
+        };
+        email_client.send(&email).await
+    }
+    .await;
+
+    match outcome {
+        Ok(()) => delete_task(transaction, &task).await?,
+        Err(e) => {
+            let n_attempts = task.n_attempts + 1;
+            if e.is_transient() && n_attempts < MAX_DELIVERY_ATTEMPTS {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to deliver issue to a confirmed subscriber. Retrying later.",
+                );
+                schedule_retry(transaction, &task, n_attempts).await?;
+            } else {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to deliver issue to a confirmed subscriber. Giving up.",
+                );
+                move_to_failed_deliveries(transaction, &task, n_attempts, &e).await?;
+            }
+        }
+    }
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+// Exponential back-off with jitter, so that retries of an issue sent
+// to many subscribers do not all hit the provider at the same time.
+pub fn retry_delay(n_attempts: i32) -> Duration {
+    let exponent = n_attempts.saturating_sub(1).clamp(0, 16) as u32;
+    let delay = BASE_RETRY_DELAY
+        .saturating_mul(2u32.pow(exponent))
+        .min(MAX_RETRY_DELAY);
+
+    // Anywhere between half and the full delay
+    delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
+struct IssueContent {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<IssueContent, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        IssueContent,
+        r#"
+        SELECT title, text_content, html_content
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(issue)
+}
+
+struct ConfirmedSubscriber {
+    id: Uuid,
+    name: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_confirmed_subscriber(
+    pool: &PgPool,
+    subscriber_email: &str,
+) -> Result<Option<ConfirmedSubscriber>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        ConfirmedSubscriber,
+        r#"SELECT id, name FROM subscriptions WHERE email = $1 AND status = $2"#,
+        subscriber_email,
+        SubscriptionStatus::Confirmed as SubscriptionStatus
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(subscriber)
+}
+
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_attempts: i32,
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(
+    pool: &PgPool,
+) -> Result<Option<(PgTransaction, DeliveryTask)>, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query_as!(
+        DeliveryTask,
+        r#"
+        SELECT newsletter_issue_id, subscriber_email, n_attempts
+        FROM issue_delivery_queue
+        WHERE next_attempt_at <= now()
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+
+    Ok(task.map(|task| (transaction, task)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email
+    );
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn schedule_retry(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+    n_attempts: i32,
+) -> Result<(), anyhow::Error> {
+    let next_attempt_at = Utc::now() + chrono::Duration::from_std(retry_delay(n_attempts))?;
+    let query = sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_attempts = $3,
+            next_attempt_at = $4
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        n_attempts,
+        next_attempt_at
+    );
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn move_to_failed_deliveries(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+    n_attempts: i32,
+    error: &EmailError,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO failed_deliveries (
+            newsletter_issue_id,
+            subscriber_email,
+            n_attempts,
+            last_error,
+            failed_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            n_attempts = EXCLUDED.n_attempts,
+            last_error = EXCLUDED.last_error,
+            failed_at = EXCLUDED.failed_at
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        n_attempts,
+        format!("{error:?}")
+    );
+    transaction.execute(query).await?;
+    // The transaction is still open, so the task can be dropped in the same go
+    delete_task(transaction, task).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{retry_delay, BASE_RETRY_DELAY, MAX_RETRY_DELAY};
+
+    #[test]
+    fn first_retry_waits_at_most_the_base_delay() {
+        for _ in 0..100 {
+            let delay = retry_delay(1);
+            assert!(delay >= BASE_RETRY_DELAY / 2 && delay <= BASE_RETRY_DELAY);
+        }
+    }
+
+    #[test]
+    fn retry_delay_grows_exponentially() {
+        for _ in 0..100 {
+            let delay = retry_delay(3);
+            assert!(delay >= BASE_RETRY_DELAY * 2 && delay <= BASE_RETRY_DELAY * 4);
+        }
+    }
+
+    #[test]
+    fn retry_delay_is_capped() {
+        for _ in 0..100 {
+            assert!(retry_delay(i32::MAX) <= MAX_RETRY_DELAY);
+        }
+    }
+}
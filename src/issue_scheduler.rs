@@ -0,0 +1,85 @@
+use sqlx::{Executor, PgPool};
+use std::time::Duration;
+use tracing::{field::display, Span};
+
+use crate::{
+    config::Settings,
+    domain::newsletter_issue_status::NewsletterIssueStatus,
+    issue_delivery_worker::{ExecutionOutcome, MAX_ERROR_BACKOFF, MIN_ERROR_BACKOFF},
+    services::newsletters::enqueue_delivery_tasks,
+    startup::get_connection_pool,
+};
+
+// Scheduled issues go out at most this late.
+const NOTHING_DUE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Publish scheduled issues as they become due, until the process is stopped.
+/// Runs next to the issue delivery worker, see `main`.
+pub async fn run_scheduler_until_stopped(config: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&config);
+
+    scheduler_loop(connection_pool).await
+}
+
+async fn scheduler_loop(pool: PgPool) -> Result<(), anyhow::Error> {
+    let mut error_backoff = MIN_ERROR_BACKOFF;
+
+    loop {
+        match try_publish_due_issue(&pool).await {
+            Ok(ExecutionOutcome::TaskCompleted) => {
+                error_backoff = MIN_ERROR_BACKOFF;
+            }
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                error_backoff = MIN_ERROR_BACKOFF;
+                tokio::time::sleep(NOTHING_DUE_POLL_INTERVAL).await;
+            }
+            Err(_) => {
+                // Already logged by `try_publish_due_issue`
+                tokio::time::sleep(error_backoff).await;
+                error_backoff = (error_backoff * 2).min(MAX_ERROR_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Publish a single scheduled issue whose `send_at` has passed: one delivery
+/// task is enqueued per subscriber confirmed at that point in time.
+/// The issue row stays locked until the transaction commits, so it cannot be
+/// rescheduled or cancelled while its deliveries are being enqueued.
+#[tracing::instrument(skip_all, fields(newsletter_issue_id=tracing::field::Empty), err)]
+pub async fn try_publish_due_issue(pool: &PgPool) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let Some(issue_id) = sqlx::query_scalar!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM newsletter_issues
+        WHERE status = $1 AND send_at <= now()
+        ORDER BY send_at
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+        NewsletterIssueStatus::Scheduled as NewsletterIssueStatus
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current().record("newsletter_issue_id", display(issue_id));
+
+    let query = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = $2, published_at = now()
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+        NewsletterIssueStatus::Published as NewsletterIssueStatus
+    );
+    transaction.execute(query).await?;
+    enqueue_delivery_tasks(&mut transaction, issue_id).await?;
+    transaction.commit().await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
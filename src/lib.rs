@@ -1,6 +1,16 @@
+pub mod authentication;
 pub mod config;
 pub mod domain;
 pub mod email_client;
+pub mod idempotency;
+pub mod issue_delivery_worker;
+pub mod issue_scheduler;
+pub mod outbox;
 pub mod services;
+pub mod session_state;
+pub mod session_store;
 pub mod startup;
+pub mod subscription_tokens;
 pub mod telemetry;
+pub mod templates;
+pub mod utils;
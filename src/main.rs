@@ -1,18 +1,104 @@
+use anyhow::Context;
+use secrecy::Secret;
+use std::fmt::{Debug, Display};
 use std::io::stdout;
-use zero2prod::config::get_config;
-use zero2prod::startup::build;
+use tokio::task::JoinError;
+use unicode_segmentation::UnicodeSegmentation;
+use zero2prod::authentication::create_user;
+use zero2prod::config::{get_config, Settings};
+use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod::issue_scheduler::run_scheduler_until_stopped;
+use zero2prod::outbox::run_dispatcher_until_stopped;
+use zero2prod::services::admin::password::{MAX_PASSWORD_LENGTH, MIN_PASSWORD_LENGTH};
+use zero2prod::startup::{get_connection_pool, Application};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 #[tokio::main]
-async fn main() -> std::io::Result<()> {
+async fn main() -> anyhow::Result<()> {
     let subscriber = get_subscriber("zero2prod", "info", stdout);
     init_subscriber(subscriber);
 
     let config = get_config().expect("Failed to read config file.");
 
-    let server = build(&config).await?;
+    // `--create-admin` adds a user to log in with, from the `ADMIN_USERNAME`
+    // and `ADMIN_PASSWORD` environment variables: no credentials ship with
+    // the application
+    if std::env::args().any(|arg| arg == "--create-admin") {
+        return create_admin(&config).await;
+    }
 
-    server.await?;
+    // `--worker-only` skips the API so that delivery workers can be scaled on their own
+    if std::env::args().any(|arg| arg == "--worker-only") {
+        let dispatcher_task = tokio::spawn(run_dispatcher_until_stopped(config.clone()));
+        let scheduler_task = tokio::spawn(run_scheduler_until_stopped(config.clone()));
+        let worker_task = tokio::spawn(run_worker_until_stopped(config));
+
+        tokio::select! {
+            o = dispatcher_task => report_exit("Outbox dispatcher", o),
+            o = scheduler_task => report_exit("Issue scheduler", o),
+            o = worker_task => report_exit("Background worker", o),
+        };
+
+        return Ok(());
+    }
+
+    let application = Application::build(&config).await?;
+    let application_task = tokio::spawn(application.run_until_stopped());
+    let dispatcher_task = tokio::spawn(run_dispatcher_until_stopped(config.clone()));
+    let scheduler_task = tokio::spawn(run_scheduler_until_stopped(config.clone()));
+    let worker_task = tokio::spawn(run_worker_until_stopped(config));
+
+    tokio::select! {
+        o = application_task => report_exit("API", o),
+        o = dispatcher_task => report_exit("Outbox dispatcher", o),
+        o = scheduler_task => report_exit("Issue scheduler", o),
+        o = worker_task => report_exit("Background worker", o),
+    };
 
     Ok(())
 }
+
+async fn create_admin(config: &Settings) -> anyhow::Result<()> {
+    let username = std::env::var("ADMIN_USERNAME").context("ADMIN_USERNAME is not set.")?;
+    let password = std::env::var("ADMIN_PASSWORD").context("ADMIN_PASSWORD is not set.")?;
+    let password_length = password.graphemes(true).count();
+    if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&password_length) {
+        anyhow::bail!(
+            "The password must be between {MIN_PASSWORD_LENGTH} and {MAX_PASSWORD_LENGTH} characters long."
+        );
+    }
+
+    let user_id = create_user(
+        &username,
+        Secret::new(password),
+        &get_connection_pool(config),
+    )
+    .await?;
+    tracing::info!(%user_id, "Created the admin user `{}`", username);
+
+    Ok(())
+}
+
+fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name)
+        }
+        Ok(Err(e)) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} failed",
+                task_name
+            )
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} task failed to complete",
+                task_name
+            )
+        }
+    }
+}
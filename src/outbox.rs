@@ -0,0 +1,378 @@
+use chrono::Utc;
+use sqlx::{Connection, Executor, PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+use crate::{
+    config::Settings,
+    domain::subscriber_email::SubscriberEmail,
+    email_client::{Email, EmailError, EmailTransport},
+    issue_delivery_worker::{
+        retry_delay, ExecutionOutcome, MAX_DELIVERY_ATTEMPTS, MAX_ERROR_BACKOFF, MIN_ERROR_BACKOFF,
+    },
+    services::subscriptions::error_chain_fmt,
+    startup::get_connection_pool,
+    subscription_tokens::issue_confirmation_email,
+    templates::EmailTemplates,
+};
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+// Subscribers are waiting for these emails: poll more often than for issues.
+const EMPTY_OUTBOX_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Write an email to the outbox, as part of `transaction`.
+/// It is only sent once the transaction commits: a rolled back change
+/// never reaches anyone's inbox, and no connection is held while the email
+/// provider is called.
+#[tracing::instrument(
+    name = "Add an email to the outbox",
+    skip(transaction, recipient, recipient_name, html_content, text_content)
+)]
+pub async fn enqueue_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    recipient: &SubscriberEmail,
+    recipient_name: Option<&str>,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO outbox (
+            id,
+            recipient_email,
+            recipient_name,
+            subject,
+            html_content,
+            text_content
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        recipient.as_ref(),
+        recipient_name,
+        subject,
+        html_content,
+        text_content
+    );
+    transaction.execute(query).await?;
+
+    Ok(())
+}
+
+/// Write a confirmation email for `subscriber_id` to the outbox, as part of
+/// `transaction`.
+/// Unlike `enqueue_email`, nothing is rendered yet: the link is issued by
+/// the dispatcher right before sending, see `issue_confirmation_email`, so
+/// the raw token never sits in the outbox.
+#[tracing::instrument(
+    name = "Add a confirmation email to the outbox",
+    skip(transaction, recipient, recipient_name)
+)]
+pub async fn enqueue_confirmation(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    recipient: &SubscriberEmail,
+    recipient_name: &str,
+    subject: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO outbox (
+            id,
+            recipient_email,
+            recipient_name,
+            subject,
+            confirmation_subscriber_id
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        recipient.as_ref(),
+        recipient_name,
+        subject,
+        subscriber_id
+    );
+    transaction.execute(query).await?;
+
+    Ok(())
+}
+
+/// Deliver outbox emails until the process is stopped.
+/// Runs next to the issue delivery worker, see `main`.
+pub async fn run_dispatcher_until_stopped(config: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&config);
+    let email_client = config.get_email_client().transport()?;
+    let templates = EmailTemplates::load(config.get_templates_dir())?;
+
+    dispatcher_loop(
+        connection_pool,
+        email_client,
+        &templates,
+        config.get_app_base_url(),
+        config.get_subscription_token_ttl(),
+    )
+    .await
+}
+
+async fn dispatcher_loop(
+    pool: PgPool,
+    email_client: Arc<dyn EmailTransport>,
+    templates: &EmailTemplates,
+    base_url: &str,
+    token_ttl: Duration,
+) -> Result<(), anyhow::Error> {
+    let mut error_backoff = MIN_ERROR_BACKOFF;
+
+    loop {
+        match try_dispatch_email(&pool, email_client.as_ref(), templates, base_url, token_ttl).await
+        {
+            Ok(ExecutionOutcome::TaskCompleted) => {
+                error_backoff = MIN_ERROR_BACKOFF;
+            }
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                error_backoff = MIN_ERROR_BACKOFF;
+                tokio::time::sleep(EMPTY_OUTBOX_POLL_INTERVAL).await;
+            }
+            Err(_) => {
+                // Already logged by `try_dispatch_email`
+                tokio::time::sleep(error_backoff).await;
+                error_backoff = (error_backoff * 2).min(MAX_ERROR_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Send a single due email from the outbox.
+/// The row is deleted in the same transaction once sent: if that fails the
+/// email goes out again later, emails are delivered at least once.
+/// Confirmation emails are rendered here, with a token issued in the same
+/// transaction: every attempt carries a fresh link and invalidates the
+/// previous ones.
+/// Transient failures, including failing to prepare the email, are retried
+/// with the back-off of issue deliveries, other failures are logged and the
+/// email dropped.
+#[tracing::instrument(
+    skip_all,
+    fields(
+        outbox_email_id=tracing::field::Empty,
+        recipient_email=tracing::field::Empty,
+        n_attempts=tracing::field::Empty
+    ),
+    err
+)]
+pub async fn try_dispatch_email(
+    pool: &PgPool,
+    email_client: &dyn EmailTransport,
+    templates: &EmailTemplates,
+    base_url: &str,
+    token_ttl: Duration,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((mut transaction, outbox_email)) = dequeue_email(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current()
+        .record("outbox_email_id", display(outbox_email.id))
+        .record("recipient_email", display(&outbox_email.recipient_email))
+        .record("n_attempts", outbox_email.n_attempts);
+
+    let outcome = match prepare_content(
+        &mut transaction,
+        &outbox_email,
+        templates,
+        base_url,
+        token_ttl,
+    )
+    .await
+    {
+        Ok((html_content, text_content)) => {
+            send_email(email_client, &outbox_email, &html_content, &text_content)
+                .await
+                .map_err(DispatchError::Send)
+        }
+        Err(e) => Err(DispatchError::Prepare(e)),
+    };
+
+    match outcome {
+        Ok(()) => delete_email(transaction, outbox_email.id).await?,
+        Err(e) => {
+            let n_attempts = outbox_email.n_attempts + 1;
+            if e.is_transient() && n_attempts < MAX_DELIVERY_ATTEMPTS {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send an email from the outbox. Retrying later.",
+                );
+                schedule_retry(transaction, outbox_email.id, n_attempts).await?;
+            } else {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send an email from the outbox. Giving up.",
+                );
+                delete_email(transaction, outbox_email.id).await?;
+            }
+        }
+    }
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+#[derive(thiserror::Error)]
+enum DispatchError {
+    #[error("Failed to prepare the email.")]
+    Prepare(#[source] anyhow::Error),
+    #[error(transparent)]
+    Send(EmailError),
+}
+
+impl DispatchError {
+    // A failure to prepare the email is most likely a database hiccup: it is
+    // retried, up to `MAX_DELIVERY_ATTEMPTS`, rather than holding up the
+    // rest of the outbox.
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Prepare(_) => true,
+            Self::Send(e) => e.is_transient(),
+        }
+    }
+}
+
+impl std::fmt::Debug for DispatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// The rendered content of `outbox_email`, issuing its confirmation link if
+/// it is a confirmation email.
+/// Runs in a savepoint: if a query fails, `transaction` can still be used to
+/// schedule a retry.
+#[tracing::instrument(skip_all)]
+async fn prepare_content(
+    transaction: &mut PgTransaction,
+    outbox_email: &OutboxEmail,
+    templates: &EmailTemplates,
+    base_url: &str,
+    token_ttl: Duration,
+) -> Result<(String, String), anyhow::Error> {
+    let Some(subscriber_id) = outbox_email.confirmation_subscriber_id else {
+        return match (&outbox_email.html_content, &outbox_email.text_content) {
+            (Some(html_content), Some(text_content)) => {
+                Ok((html_content.clone(), text_content.clone()))
+            }
+            _ => Err(anyhow::anyhow!("The email has no content.")),
+        };
+    };
+
+    let mut savepoint = transaction.begin().await?;
+    let email = issue_confirmation_email(
+        &mut savepoint,
+        templates,
+        subscriber_id,
+        outbox_email.recipient_name.as_deref().unwrap_or_default(),
+        base_url,
+        token_ttl,
+    )
+    .await?;
+    savepoint.commit().await?;
+
+    Ok((email.html_content, email.text_content))
+}
+
+async fn send_email(
+    email_client: &dyn EmailTransport,
+    outbox_email: &OutboxEmail,
+    html_content: &str,
+    text_content: &str,
+) -> Result<(), EmailError> {
+    let recipient = SubscriberEmail::parse(outbox_email.recipient_email.clone()).map_err(|e| {
+        EmailError::Permanent(anyhow::anyhow!(e).context("The recipient of the email is invalid."))
+    })?;
+    let email = Email {
+        from: email_client.sender(),
+        to: &recipient,
+        to_name: outbox_email.recipient_name.as_deref(),
+        subject: &outbox_email.subject,
+        html_content,
+        text_content,
+        unsubscribe_url: None,
+    };
+
+    email_client.send(&email).await
+}
+
+struct OutboxEmail {
+    id: Uuid,
+    recipient_email: String,
+    recipient_name: Option<String>,
+    subject: String,
+    // Either rendered content, or the subscriber to render a confirmation
+    // email for
+    html_content: Option<String>,
+    text_content: Option<String>,
+    confirmation_subscriber_id: Option<Uuid>,
+    n_attempts: i32,
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_email(
+    pool: &PgPool,
+) -> Result<Option<(PgTransaction, OutboxEmail)>, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let outbox_email = sqlx::query_as!(
+        OutboxEmail,
+        r#"
+        SELECT
+            id,
+            recipient_email,
+            recipient_name,
+            subject,
+            html_content,
+            text_content,
+            confirmation_subscriber_id,
+            n_attempts
+        FROM outbox
+        WHERE next_attempt_at <= now()
+        ORDER BY created_at
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+
+    Ok(outbox_email.map(|e| (transaction, e)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_email(mut transaction: PgTransaction, id: Uuid) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(r#"DELETE FROM outbox WHERE id = $1"#, id);
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn schedule_retry(
+    mut transaction: PgTransaction,
+    id: Uuid,
+    n_attempts: i32,
+) -> Result<(), anyhow::Error> {
+    let next_attempt_at = Utc::now() + chrono::Duration::from_std(retry_delay(n_attempts))?;
+    let query = sqlx::query!(
+        r#"UPDATE outbox SET n_attempts = $2, next_attempt_at = $3 WHERE id = $1"#,
+        id,
+        n_attempts,
+        next_attempt_at
+    );
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
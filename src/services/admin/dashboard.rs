@@ -0,0 +1,82 @@
+use actix_web::http::header::ContentType;
+use actix_web::{get, web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{authentication::UserId, domain::subscription_status::SubscriptionStatus, utils::e500};
+
+#[get("/dashboard")]
+async fn admin_dashboard(
+    user_id: web::ReqData<UserId>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let username = get_username(**user_id, &pool).await.map_err(e500)?;
+    let subscriber_counts = get_subscriber_counts(&pool).await.map_err(e500)?;
+
+    let counts_html: String = subscriber_counts
+        .iter()
+        .map(|(status, count)| format!("<li>{status}: {count}</li>"))
+        .collect();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Admin dashboard</title>
+</head>
+<body>
+    <p>Welcome {}!</p>
+    <p>Subscribers:</p>
+    <ul>{counts_html}</ul>
+    <p>Available actions:</p>
+    <ol>
+        <li><a href="/admin/newsletters">Send a newsletter issue</a></li>
+        <li><a href="/admin/drafts">Manage drafts</a></li>
+        <li><a href="/admin/scheduled-issues">Manage scheduled issues</a></li>
+        <li><a href="/admin/failed-deliveries">Review failed deliveries</a></li>
+        <li><a href="/admin/templates">Preview email templates</a></li>
+        <li><a href="/admin/password">Change password</a></li>
+        <li>
+            <form name="logoutForm" action="/admin/logout" method="post">
+                <input type="submit" value="Logout">
+            </form>
+        </li>
+    </ol>
+</body>
+</html>"#,
+            htmlescape::encode_minimal(&username)
+        )))
+}
+
+#[tracing::instrument(name = "Get username", skip(pool))]
+pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow::Error> {
+    let row = sqlx::query!(r#"SELECT username FROM users WHERE user_id = $1"#, user_id)
+        .fetch_one(pool)
+        .await
+        .context("Failed to perform a query to retrieve a username.")?;
+
+    Ok(row.username)
+}
+
+#[tracing::instrument(name = "Count subscribers by status", skip(pool))]
+async fn get_subscriber_counts(
+    pool: &PgPool,
+) -> Result<Vec<(SubscriptionStatus, i64)>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT status AS "status: SubscriptionStatus", COUNT(*) AS "count!"
+        FROM subscriptions
+        GROUP BY status
+        ORDER BY status
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to count the subscribers by status.")?;
+
+    Ok(rows.into_iter().map(|r| (r.status, r.count)).collect())
+}
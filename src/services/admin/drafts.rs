@@ -0,0 +1,721 @@
+use actix_web::http::header::ContentType;
+use actix_web::{get, post, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use similar::{ChangeTag, TextDiff};
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::UserId,
+    domain::{newsletter_issue::NewsletterIssue, send_at::SendAt},
+    idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+    services::{admin::newsletters::success_message, newsletters::enqueue_issue},
+    session_state::TypedSession,
+    utils::{e400, e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+struct DraftFormData {
+    title: String,
+    markdown_content: String,
+}
+
+#[derive(serde::Deserialize)]
+struct PublishFormData {
+    // RFC 3339, the issue goes out right away if left empty
+    #[serde(default)]
+    send_at: String,
+    idempotency_key: String,
+}
+
+#[derive(serde::Deserialize)]
+struct DiffQuery {
+    // The revision to compare with, the previous one by default
+    against: Option<i32>,
+}
+
+struct DraftSummary {
+    draft_id: Uuid,
+    title: String,
+    latest_revision: i32,
+    updated_at: DateTime<Utc>,
+    newsletter_issue_id: Option<Uuid>,
+}
+
+struct Revision {
+    revision: i32,
+    title: String,
+    markdown_content: String,
+    author: String,
+    created_at: DateTime<Utc>,
+}
+
+enum SaveOutcome {
+    Saved(i32),
+    Unchanged,
+    AlreadyPublished,
+    DraftNotFound,
+}
+
+fn draft_not_found(draft_id: Uuid) -> actix_web::Error {
+    actix_web::error::ErrorNotFound(format!("There is no draft with id `{draft_id}`."))
+}
+
+fn flash_html(session: &TypedSession) -> String {
+    match session.take_flash() {
+        Some(message) => format!("<p><i>{}</i></p>", htmlescape::encode_minimal(&message)),
+        None => String::new(),
+    }
+}
+
+#[get("/drafts")]
+async fn list_drafts(
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let message_html = flash_html(&session);
+    let drafts = get_drafts(&pool).await.map_err(e500)?;
+
+    let rows_html: String = drafts
+        .iter()
+        .map(|d| {
+            let title = match d.title.trim() {
+                "" => "(untitled)".to_string(),
+                title => htmlescape::encode_minimal(title),
+            };
+            let status = match d.newsletter_issue_id {
+                Some(_) => "Published",
+                None => "Draft",
+            };
+            format!(
+                r#"<tr>
+            <td><a href="/admin/drafts/{}">{title}</a></td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{status}</td>
+        </tr>"#,
+                d.draft_id,
+                d.latest_revision,
+                d.updated_at.to_rfc3339(),
+            )
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Drafts</title>
+</head>
+<body>
+    {message_html}
+    <table>
+        <tr>
+            <th>Title</th>
+            <th>Revisions</th>
+            <th>Last saved at</th>
+            <th>Status</th>
+        </tr>
+        {rows_html}
+    </table>
+    <p>New draft:</p>
+    <form action="/admin/drafts" method="post">
+        <label>Title:<br>
+            <input type="text" placeholder="Enter the issue title" name="title">
+        </label>
+        <br>
+        <label>Markdown content:<br>
+            <textarea placeholder="Enter the content in Markdown" name="markdown_content" rows="20" cols="50"></textarea>
+        </label>
+        <br>
+        <button type="submit">Save</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Create a draft", skip_all, fields(user_id = %*user_id))]
+#[post("/drafts")]
+async fn create_draft(
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = Uuid::new_v4();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let query = sqlx::query!(
+        r#"INSERT INTO drafts (draft_id, created_at) VALUES ($1, now())"#,
+        draft_id
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to store the new draft.")
+        .map_err(e500)?;
+    insert_revision(&mut transaction, draft_id, 1, &form, **user_id)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction to create a draft.")
+        .map_err(e500)?;
+
+    session
+        .insert_flash("The draft has been saved.")
+        .map_err(e500)?;
+    Ok(see_other(&format!("/admin/drafts/{draft_id}")))
+}
+
+#[get("/drafts/{draft_id}")]
+async fn edit_draft_form(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let message_html = flash_html(&session);
+    let Some(is_published) = get_publication_state(&pool, draft_id).await.map_err(e500)? else {
+        return Err(draft_not_found(draft_id));
+    };
+    let revisions = get_revisions(&pool, draft_id).await.map_err(e500)?;
+    let latest = revisions
+        .first()
+        .context("A draft has at least one revision.")
+        .map_err(e500)?;
+
+    // A fresh key per rendered form, as for `publish_newsletter_form`
+    let idempotency_key = Uuid::new_v4();
+    let actions_html = if is_published {
+        "<p>This draft has been published, it can no longer be edited.</p>".to_string()
+    } else {
+        format!(
+            r#"<form action="/admin/drafts/{draft_id}" method="post">
+        <label>Title:<br>
+            <input type="text" placeholder="Enter the issue title" name="title" value="{}">
+        </label>
+        <br>
+        <label>Markdown content:<br>
+            <textarea placeholder="Enter the content in Markdown" name="markdown_content" rows="20" cols="50">{}</textarea>
+        </label>
+        <br>
+        <button type="submit">Save</button>
+    </form>
+    <form action="/admin/drafts/{draft_id}/publish" method="post">
+        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+        <label>Send at (optional):<br>
+            <input type="text" placeholder="e.g. 2024-01-02T09:00:00+01:00" name="send_at">
+        </label>
+        <br>
+        <button type="submit">Publish</button>
+    </form>"#,
+            htmlescape::encode_attribute(&latest.title),
+            htmlescape::encode_minimal(&latest.markdown_content),
+        )
+    };
+    let revisions_html: String = revisions
+        .iter()
+        .map(|r| {
+            let restore_html = if is_published || r.revision == latest.revision {
+                String::new()
+            } else {
+                format!(
+                    r#"<form action="/admin/drafts/{draft_id}/revisions/{}/restore" method="post">
+                    <button type="submit">Restore</button>
+                </form>"#,
+                    r.revision
+                )
+            };
+            format!(
+                r#"<li>
+                <a href="/admin/drafts/{draft_id}/revisions/{revision}">Revision {revision}</a>,
+                saved by {} at {}
+                {restore_html}
+            </li>"#,
+                htmlescape::encode_minimal(&r.author),
+                r.created_at.to_rfc3339(),
+                revision = r.revision,
+            )
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Edit draft</title>
+</head>
+<body>
+    {message_html}
+    {actions_html}
+    <p>Revisions:</p>
+    <ul>{revisions_html}</ul>
+    <form action="/admin/drafts/{draft_id}/delete" method="post">
+        <button type="submit">Delete the draft</button>
+    </form>
+    <p><a href="/admin/drafts">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Save a draft", skip_all, fields(user_id = %*user_id, draft_id = %draft_id))]
+#[post("/drafts/{draft_id}")]
+async fn save_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let outcome = add_revision(&pool, draft_id, &form, **user_id)
+        .await
+        .map_err(e500)?;
+
+    let message = match outcome {
+        SaveOutcome::Saved(_) => "The draft has been saved.",
+        SaveOutcome::Unchanged => "There are no changes to save.",
+        SaveOutcome::AlreadyPublished => {
+            "This draft has been published, it can no longer be edited."
+        }
+        SaveOutcome::DraftNotFound => return Err(draft_not_found(draft_id)),
+    };
+    session.insert_flash(message).map_err(e500)?;
+
+    Ok(see_other(&format!("/admin/drafts/{draft_id}")))
+}
+
+/// Show a revision, as a diff against an older one.
+#[get("/drafts/{draft_id}/revisions/{revision}")]
+async fn revision_diff(
+    path: web::Path<(Uuid, i32)>,
+    query: web::Query<DiffQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (draft_id, revision) = path.into_inner();
+    let Some(new) = get_revision(&pool, draft_id, revision)
+        .await
+        .map_err(e500)?
+    else {
+        return Err(actix_web::error::ErrorNotFound(format!(
+            "There is no revision {revision} of this draft."
+        )));
+    };
+    let against = query.against.unwrap_or(revision - 1);
+    // The first revision is compared with an empty draft
+    let (old_title, old_content) =
+        match get_revision(&pool, draft_id, against).await.map_err(e500)? {
+            Some(old) => (old.title, old.markdown_content),
+            None => (String::new(), String::new()),
+        };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Revision {revision}</title>
+</head>
+<body>
+    <p>Revision {revision}, saved by {} at {}, compared with revision {against}:</p>
+    <p>Title:</p>
+    <pre>{}</pre>
+    <p>Markdown content:</p>
+    <pre>{}</pre>
+    <p><a href="/admin/drafts/{draft_id}">&lt;- Back</a></p>
+</body>
+</html>"#,
+            htmlescape::encode_minimal(&new.author),
+            new.created_at.to_rfc3339(),
+            diff_html(&old_title, &new.title),
+            diff_html(&old_content, &new.markdown_content),
+        )))
+}
+
+/// Restoring a revision saves its content as a new revision: the history
+/// is never rewritten.
+#[tracing::instrument(name = "Restore a draft revision", skip_all, fields(user_id = %*user_id))]
+#[post("/drafts/{draft_id}/revisions/{revision}/restore")]
+async fn restore_revision(
+    path: web::Path<(Uuid, i32)>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (draft_id, revision) = path.into_inner();
+    let Some(restored) = get_revision(&pool, draft_id, revision)
+        .await
+        .map_err(e500)?
+    else {
+        return Err(actix_web::error::ErrorNotFound(format!(
+            "There is no revision {revision} of this draft."
+        )));
+    };
+    let content = DraftFormData {
+        title: restored.title,
+        markdown_content: restored.markdown_content,
+    };
+    let outcome = add_revision(&pool, draft_id, &content, **user_id)
+        .await
+        .map_err(e500)?;
+
+    let message = match outcome {
+        SaveOutcome::Saved(new_revision) => {
+            format!("Revision {revision} has been restored as revision {new_revision}.")
+        }
+        SaveOutcome::Unchanged => format!("Revision {revision} is the current content."),
+        SaveOutcome::AlreadyPublished => {
+            "This draft has been published, it can no longer be edited.".into()
+        }
+        SaveOutcome::DraftNotFound => return Err(draft_not_found(draft_id)),
+    };
+    session.insert_flash(&message).map_err(e500)?;
+
+    Ok(see_other(&format!("/admin/drafts/{draft_id}")))
+}
+
+#[tracing::instrument(name = "Delete a draft", skip_all, fields(draft_id = %draft_id))]
+#[post("/drafts/{draft_id}/delete")]
+async fn delete_draft(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    // Revisions go along with the draft, published issues stay
+    let n_deleted_rows = sqlx::query!(r#"DELETE FROM drafts WHERE draft_id = $1"#, draft_id)
+        .execute(pool.get_ref())
+        .await
+        .context("Failed to delete the draft.")
+        .map_err(e500)?
+        .rows_affected();
+
+    let message = if n_deleted_rows == 1 {
+        "The draft has been deleted."
+    } else {
+        "The draft had already been deleted."
+    };
+    session.insert_flash(message).map_err(e500)?;
+
+    Ok(see_other("/admin/drafts"))
+}
+
+/// Publish the latest revision of a draft, like `publish_newsletter` does for
+/// the compose form: the draft then points to its (immutable) issue and
+/// cannot be edited anymore.
+#[tracing::instrument(
+    name = "Publish a draft",
+    skip_all,
+    fields(user_id = %*user_id, draft_id = %draft_id)
+)]
+#[post("/drafts/{draft_id}/publish")]
+async fn publish_draft(
+    draft_id: web::Path<Uuid>,
+    form: web::Form<PublishFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let user_id = user_id.into_inner();
+    let draft_url = format!("/admin/drafts/{draft_id}");
+    let PublishFormData {
+        send_at,
+        idempotency_key,
+    } = form.0;
+    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let send_at = match send_at.trim() {
+        "" => None,
+        send_at => match SendAt::parse(send_at) {
+            Ok(send_at) => Some(send_at),
+            Err(e) => {
+                session.insert_flash(&e).map_err(e500)?;
+                return Ok(see_other(&draft_url));
+            }
+        },
+    };
+
+    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            success_message(&session, send_at)?;
+            return Ok(saved_response);
+        }
+    };
+
+    // Bailing out drops the transaction: nothing is saved for the key either
+    match lock_draft(&mut transaction, draft_id).await.map_err(e500)? {
+        None => return Err(draft_not_found(draft_id)),
+        Some(true) => {
+            session
+                .insert_flash("This draft has already been published.")
+                .map_err(e500)?;
+            return Ok(see_other(&draft_url));
+        }
+        Some(false) => {}
+    }
+    let latest = get_latest_revision(&mut transaction, draft_id)
+        .await
+        .map_err(e500)?;
+    let issue = match NewsletterIssue::from_markdown(latest.title, latest.markdown_content) {
+        Ok(issue) => issue,
+        Err(e) => {
+            session.insert_flash(&e).map_err(e500)?;
+            return Ok(see_other(&draft_url));
+        }
+    };
+
+    let issue_id = enqueue_issue(&mut transaction, &issue, send_at)
+        .await
+        .map_err(e500)?;
+    let query = sqlx::query!(
+        r#"UPDATE drafts SET newsletter_issue_id = $2 WHERE draft_id = $1"#,
+        draft_id,
+        issue_id
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to link the draft to its issue.")
+        .map_err(e500)?;
+
+    success_message(&session, send_at)?;
+    let response = see_other(&draft_url);
+    let response = save_response(transaction, &idempotency_key, *user_id, response)
+        .await
+        .map_err(e500)?;
+
+    Ok(response)
+}
+
+// Removed and added lines are marked with `-` and `+`.
+fn diff_html(old: &str, new: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|change| {
+            let line = htmlescape::encode_minimal(change.value().trim_end_matches('\n'));
+            match change.tag() {
+                ChangeTag::Delete => format!("<del>- {line}</del>\n"),
+                ChangeTag::Insert => format!("<ins>+ {line}</ins>\n"),
+                ChangeTag::Equal => format!("  {line}\n"),
+            }
+        })
+        .collect()
+}
+
+#[tracing::instrument(name = "Get drafts", skip(pool))]
+async fn get_drafts(pool: &PgPool) -> Result<Vec<DraftSummary>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        DraftSummary,
+        r#"
+        SELECT
+            d.draft_id,
+            r.title,
+            r.revision AS latest_revision,
+            r.created_at AS updated_at,
+            d.newsletter_issue_id
+        FROM drafts d
+        JOIN draft_revisions r ON r.draft_id = d.draft_id
+        WHERE r.revision = (
+            SELECT MAX(revision) FROM draft_revisions WHERE draft_id = d.draft_id
+        )
+        ORDER BY r.created_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the drafts.")?;
+
+    Ok(rows)
+}
+
+// Whether the draft has been published, `None` if there is no such draft.
+#[tracing::instrument(name = "Get the publication state of a draft", skip(pool))]
+async fn get_publication_state(
+    pool: &PgPool,
+    draft_id: Uuid,
+) -> Result<Option<bool>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT newsletter_issue_id FROM drafts WHERE draft_id = $1"#,
+        draft_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the draft.")?;
+
+    Ok(row.map(|r| r.newsletter_issue_id.is_some()))
+}
+
+// Same as `get_publication_state`, with the draft locked until the
+// transaction ends: saves and publications of a draft are serialised.
+#[tracing::instrument(name = "Lock a draft", skip(transaction))]
+async fn lock_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+) -> Result<Option<bool>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT newsletter_issue_id FROM drafts WHERE draft_id = $1 FOR UPDATE"#,
+        draft_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .context("Failed to lock the draft.")?;
+
+    Ok(row.map(|r| r.newsletter_issue_id.is_some()))
+}
+
+/// Newest first.
+#[tracing::instrument(name = "Get the revisions of a draft", skip(pool))]
+async fn get_revisions(pool: &PgPool, draft_id: Uuid) -> Result<Vec<Revision>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        Revision,
+        r#"
+        SELECT r.revision, r.title, r.markdown_content, u.username AS author, r.created_at
+        FROM draft_revisions r
+        JOIN users u ON u.user_id = r.author_id
+        WHERE r.draft_id = $1
+        ORDER BY r.revision DESC
+        "#,
+        draft_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the revisions of the draft.")?;
+
+    Ok(rows)
+}
+
+#[tracing::instrument(name = "Get a revision of a draft", skip(pool))]
+async fn get_revision(
+    pool: &PgPool,
+    draft_id: Uuid,
+    revision: i32,
+) -> Result<Option<Revision>, anyhow::Error> {
+    let row = sqlx::query_as!(
+        Revision,
+        r#"
+        SELECT r.revision, r.title, r.markdown_content, u.username AS author, r.created_at
+        FROM draft_revisions r
+        JOIN users u ON u.user_id = r.author_id
+        WHERE r.draft_id = $1 AND r.revision = $2
+        "#,
+        draft_id,
+        revision
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the revision of the draft.")?;
+
+    Ok(row)
+}
+
+#[tracing::instrument(name = "Get the latest revision of a draft", skip(transaction))]
+async fn get_latest_revision(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+) -> Result<Revision, anyhow::Error> {
+    let row = sqlx::query_as!(
+        Revision,
+        r#"
+        SELECT r.revision, r.title, r.markdown_content, u.username AS author, r.created_at
+        FROM draft_revisions r
+        JOIN users u ON u.user_id = r.author_id
+        WHERE r.draft_id = $1
+        ORDER BY r.revision DESC
+        LIMIT 1
+        "#,
+        draft_id
+    )
+    .fetch_one(&mut **transaction)
+    .await
+    .context("Failed to retrieve the latest revision of the draft.")?;
+
+    Ok(row)
+}
+
+// Save `content` as the next revision of the draft, unless it is the same
+// as the latest one.
+#[tracing::instrument(name = "Add a revision to a draft", skip(pool, content))]
+async fn add_revision(
+    pool: &PgPool,
+    draft_id: Uuid,
+    content: &DraftFormData,
+    author_id: Uuid,
+) -> Result<SaveOutcome, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    match lock_draft(&mut transaction, draft_id).await? {
+        None => return Ok(SaveOutcome::DraftNotFound),
+        Some(true) => return Ok(SaveOutcome::AlreadyPublished),
+        Some(false) => {}
+    }
+    let latest = get_latest_revision(&mut transaction, draft_id).await?;
+    if latest.title == content.title && latest.markdown_content == content.markdown_content {
+        return Ok(SaveOutcome::Unchanged);
+    }
+
+    let revision = latest.revision + 1;
+    insert_revision(&mut transaction, draft_id, revision, content, author_id).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction to save a draft.")?;
+
+    Ok(SaveOutcome::Saved(revision))
+}
+
+#[tracing::instrument(skip_all)]
+async fn insert_revision(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+    revision: i32,
+    content: &DraftFormData,
+    author_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO draft_revisions (
+            draft_id,
+            revision,
+            title,
+            markdown_content,
+            author_id,
+            created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        draft_id,
+        revision,
+        content.title,
+        content.markdown_content,
+        author_id
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to store the draft revision.")?;
+
+    Ok(())
+}
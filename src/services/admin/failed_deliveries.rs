@@ -0,0 +1,190 @@
+use actix_web::http::header::ContentType;
+use actix_web::{get, post, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+struct FormData {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+}
+
+struct FailedDelivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+    subscriber_email: String,
+    n_attempts: i32,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Show the failed deliveries", skip_all)]
+#[get("/failed-deliveries")]
+async fn failed_deliveries(
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let message_html = match session.take_flash() {
+        Some(message) => format!("<p><i>{}</i></p>", htmlescape::encode_minimal(&message)),
+        None => String::new(),
+    };
+    let deliveries = get_failed_deliveries(&pool).await.map_err(e500)?;
+
+    let rows_html: String = deliveries
+        .iter()
+        .map(|d| {
+            format!(
+                r#"<tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>
+                <form action="/admin/failed-deliveries/retry" method="post">
+                    <input hidden type="text" name="newsletter_issue_id" value="{}">
+                    <input hidden type="text" name="subscriber_email" value="{}">
+                    <button type="submit">Retry</button>
+                </form>
+            </td>
+        </tr>"#,
+                htmlescape::encode_minimal(&d.title),
+                htmlescape::encode_minimal(&d.subscriber_email),
+                d.n_attempts,
+                d.failed_at.to_rfc3339(),
+                htmlescape::encode_minimal(&d.last_error),
+                d.newsletter_issue_id,
+                htmlescape::encode_attribute(&d.subscriber_email),
+            )
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Failed deliveries</title>
+</head>
+<body>
+    {message_html}
+    <table>
+        <tr>
+            <th>Issue</th>
+            <th>Subscriber</th>
+            <th>Attempts</th>
+            <th>Failed at</th>
+            <th>Last error</th>
+            <th></th>
+        </tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Retry a failed delivery", skip_all, fields(newsletter_issue_id = %form.newsletter_issue_id))]
+#[post("/failed-deliveries/retry")]
+async fn retry_failed_delivery(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let requeued = requeue_failed_delivery(&pool, form.newsletter_issue_id, &form.subscriber_email)
+        .await
+        .map_err(e500)?;
+
+    let message = if requeued {
+        "The delivery has been put back in the queue."
+    } else {
+        "The delivery is no longer in the failed deliveries."
+    };
+    session.insert_flash(message).map_err(e500)?;
+
+    Ok(see_other("/admin/failed-deliveries"))
+}
+
+#[tracing::instrument(name = "Get failed deliveries", skip(pool))]
+async fn get_failed_deliveries(pool: &PgPool) -> Result<Vec<FailedDelivery>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        FailedDelivery,
+        r#"
+        SELECT
+            f.newsletter_issue_id,
+            i.title,
+            f.subscriber_email,
+            f.n_attempts,
+            f.last_error,
+            f.failed_at
+        FROM failed_deliveries f
+        JOIN newsletter_issues i ON i.newsletter_issue_id = f.newsletter_issue_id
+        ORDER BY f.failed_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the failed deliveries.")?;
+
+    Ok(rows)
+}
+
+// Move the delivery back to the queue with a fresh attempt counter.
+// Returns `false` if there was no such failed delivery (e.g. it was already retried).
+#[tracing::instrument(name = "Re-enqueue a failed delivery", skip(pool))]
+async fn requeue_failed_delivery(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    subscriber_email: &str,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM failed_deliveries
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        "#,
+        newsletter_issue_id,
+        subscriber_email
+    );
+    let n_deleted_rows = transaction
+        .execute(query)
+        .await
+        .context("Failed to remove the failed delivery.")?
+        .rows_affected();
+    if n_deleted_rows == 0 {
+        return Ok(false);
+    }
+
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issue_id,
+        subscriber_email
+    );
+    transaction
+        .execute(query)
+        .await
+        .context("Failed to enqueue the delivery.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction to re-enqueue a delivery.")?;
+
+    Ok(true)
+}
@@ -0,0 +1,16 @@
+use actix_web::{post, HttpResponse};
+
+use crate::{
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+#[post("/logout")]
+async fn log_out(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
+    session.log_out();
+    session
+        .insert_flash("You have successfully logged out.")
+        .map_err(e500)?;
+
+    Ok(see_other("/login"))
+}
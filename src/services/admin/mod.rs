@@ -0,0 +1,8 @@
+pub mod dashboard;
+pub mod drafts;
+pub mod failed_deliveries;
+pub mod logout;
+pub mod newsletters;
+pub mod password;
+pub mod scheduled_issues;
+pub mod templates;
@@ -0,0 +1,157 @@
+use actix_web::http::header::ContentType;
+use actix_web::{get, post, web, HttpResponse};
+use sqlx::PgPool;
+
+use crate::{
+    authentication::UserId,
+    domain::{newsletter_issue::NewsletterIssue, send_at::SendAt},
+    idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+    services::newsletters::enqueue_issue,
+    session_state::TypedSession,
+    utils::{e400, e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+struct FormData {
+    title: String,
+    // Takes precedence over the other two bodies when filled in
+    #[serde(default)]
+    markdown_content: String,
+    #[serde(default)]
+    html_content: String,
+    #[serde(default)]
+    text_content: String,
+    // RFC 3339, the issue goes out right away if left empty
+    #[serde(default)]
+    send_at: String,
+    idempotency_key: String,
+}
+
+#[get("/newsletters")]
+async fn publish_newsletter_form(session: TypedSession) -> HttpResponse {
+    let message_html = match session.take_flash() {
+        Some(message) => format!("<p><i>{}</i></p>", htmlescape::encode_minimal(&message)),
+        None => String::new(),
+    };
+    // A fresh key per rendered form: submitting the same form twice
+    // (e.g. a double click) publishes the issue only once.
+    let idempotency_key = uuid::Uuid::new_v4();
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Publish Newsletter Issue</title>
+</head>
+<body>
+    {message_html}
+    <form action="/admin/newsletters" method="post">
+        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+        <label>Title:<br>
+            <input type="text" placeholder="Enter the issue title" name="title">
+        </label>
+        <br>
+        <label>Markdown content:<br>
+            <textarea placeholder="Enter the content in Markdown, or fill in both fields below" name="markdown_content" rows="20" cols="50"></textarea>
+        </label>
+        <br>
+        <label>Plain text content:<br>
+            <textarea placeholder="Enter the content in plain text" name="text_content" rows="20" cols="50"></textarea>
+        </label>
+        <br>
+        <label>HTML content:<br>
+            <textarea placeholder="Enter the content in HTML format" name="html_content" rows="20" cols="50"></textarea>
+        </label>
+        <br>
+        <label>Send at (optional):<br>
+            <input type="text" placeholder="e.g. 2024-01-02T09:00:00+01:00" name="send_at">
+        </label>
+        <br>
+        <button type="submit">Publish</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        ))
+}
+
+#[tracing::instrument(
+    name = "Publish a newsletter issue from the admin form",
+    skip_all,
+    fields(user_id = %*user_id)
+)]
+#[post("/newsletters")]
+async fn publish_newsletter(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let FormData {
+        title,
+        markdown_content,
+        html_content,
+        text_content,
+        send_at,
+        idempotency_key,
+    } = form.0;
+    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+
+    let issue = if markdown_content.trim().is_empty() {
+        NewsletterIssue::parse(title, html_content, text_content)
+    } else {
+        NewsletterIssue::from_markdown(title, markdown_content)
+    };
+    let send_at = match send_at.trim() {
+        "" => Ok(None),
+        send_at => SendAt::parse(send_at).map(Some),
+    };
+    let (issue, send_at) = match issue.and_then(|issue| send_at.map(|send_at| (issue, send_at))) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            session.insert_flash(&e).map_err(e500)?;
+            return Ok(see_other("/admin/newsletters"));
+        }
+    };
+
+    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            success_message(&session, send_at)?;
+            return Ok(saved_response);
+        }
+    };
+
+    enqueue_issue(&mut transaction, &issue, send_at)
+        .await
+        .map_err(e500)?;
+
+    success_message(&session, send_at)?;
+    let response = see_other("/admin/newsletters");
+    let response = save_response(transaction, &idempotency_key, *user_id, response)
+        .await
+        .map_err(e500)?;
+
+    Ok(response)
+}
+
+pub fn success_message(
+    session: &TypedSession,
+    send_at: Option<SendAt>,
+) -> Result<(), actix_web::Error> {
+    let message = match send_at.filter(SendAt::is_in_the_future) {
+        Some(send_at) => format!(
+            "The newsletter issue has been scheduled - emails will go out at {}.",
+            send_at.as_ref().to_rfc3339()
+        ),
+        None => "The newsletter issue has been accepted - emails will go out shortly.".into(),
+    };
+    session.insert_flash(&message).map_err(e500)
+}
@@ -0,0 +1,118 @@
+use actix_web::http::header::ContentType;
+use actix_web::{get, post, web, HttpResponse};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::dashboard::get_username;
+use crate::{
+    authentication::{self, validate_credentials, AuthError, Credentials, UserId},
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+pub const MIN_PASSWORD_LENGTH: usize = 12;
+pub const MAX_PASSWORD_LENGTH: usize = 128;
+
+#[derive(serde::Deserialize)]
+struct FormData {
+    current_password: Secret<String>,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[get("/password")]
+async fn change_password_form(session: TypedSession) -> HttpResponse {
+    let message_html = match session.take_flash() {
+        Some(message) => format!("<p><i>{}</i></p>", htmlescape::encode_minimal(&message)),
+        None => String::new(),
+    };
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Change Password</title>
+</head>
+<body>
+    {message_html}
+    <form action="/admin/password" method="post">
+        <label>Current password
+            <input type="password" placeholder="Enter current password" name="current_password">
+        </label>
+        <br>
+        <label>New password
+            <input type="password" placeholder="Enter new password" name="new_password">
+        </label>
+        <br>
+        <label>Confirm new password
+            <input type="password" placeholder="Type the new password again" name="new_password_check">
+        </label>
+        <br>
+        <button type="submit">Change password</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        ))
+}
+
+#[tracing::instrument(name = "Change password", skip_all, fields(user_id = %*user_id))]
+#[post("/password")]
+async fn change_password(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        return password_redirect(
+            &session,
+            "You entered two different new passwords - the field values must match.",
+        );
+    }
+
+    let new_password_length = form.new_password.expose_secret().graphemes(true).count();
+    if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&new_password_length) {
+        return password_redirect(
+            &session,
+            &format!(
+                "The new password must be between {MIN_PASSWORD_LENGTH} and {MAX_PASSWORD_LENGTH} characters long."
+            ),
+        );
+    }
+
+    let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    let credentials = Credentials {
+        username,
+        password: form.0.current_password,
+    };
+    if let Err(e) = validate_credentials(credentials, &pool).await {
+        return match e {
+            AuthError::InvalidCredentials(_) => {
+                password_redirect(&session, "The current password is incorrect.")
+            }
+            AuthError::UnexpectedError(_) => Err(e500(e)),
+        };
+    }
+
+    authentication::change_password(*user_id, form.0.new_password, &pool)
+        .await
+        .map_err(e500)?;
+
+    password_redirect(&session, "Your password has been changed.")
+}
+
+fn password_redirect(
+    session: &TypedSession,
+    message: &str,
+) -> Result<HttpResponse, actix_web::Error> {
+    session.insert_flash(message).map_err(e500)?;
+
+    Ok(see_other("/admin/password"))
+}
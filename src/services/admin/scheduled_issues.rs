@@ -0,0 +1,214 @@
+use actix_web::http::header::ContentType;
+use actix_web::{get, post, web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    domain::{newsletter_issue_status::NewsletterIssueStatus, send_at::SendAt},
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+struct RescheduleFormData {
+    newsletter_issue_id: Uuid,
+    send_at: String,
+}
+
+#[derive(serde::Deserialize)]
+struct CancelFormData {
+    newsletter_issue_id: Uuid,
+}
+
+struct ScheduledIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    send_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Show the scheduled issues", skip_all)]
+#[get("/scheduled-issues")]
+async fn scheduled_issues(
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let message_html = match session.take_flash() {
+        Some(message) => format!("<p><i>{}</i></p>", htmlescape::encode_minimal(&message)),
+        None => String::new(),
+    };
+    let issues = get_scheduled_issues(&pool).await.map_err(e500)?;
+
+    let rows_html: String = issues
+        .iter()
+        .map(|issue| {
+            let send_at = issue.send_at.map(|t| t.to_rfc3339()).unwrap_or_default();
+            format!(
+                r#"<tr>
+            <td>{}</td>
+            <td>{send_at}</td>
+            <td>
+                <form action="/admin/scheduled-issues/reschedule" method="post">
+                    <input hidden type="text" name="newsletter_issue_id" value="{id}">
+                    <input type="text" name="send_at" value="{send_at}">
+                    <button type="submit">Reschedule</button>
+                </form>
+            </td>
+            <td>
+                <form action="/admin/scheduled-issues/cancel" method="post">
+                    <input hidden type="text" name="newsletter_issue_id" value="{id}">
+                    <button type="submit">Cancel</button>
+                </form>
+            </td>
+        </tr>"#,
+                htmlescape::encode_minimal(&issue.title),
+                id = issue.newsletter_issue_id,
+            )
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Scheduled issues</title>
+</head>
+<body>
+    {message_html}
+    <table>
+        <tr>
+            <th>Issue</th>
+            <th>Send at</th>
+            <th></th>
+            <th></th>
+        </tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+/// A send time in the past sends the issue on the next run of the scheduler.
+#[tracing::instrument(name = "Reschedule an issue", skip_all, fields(newsletter_issue_id = %form.newsletter_issue_id))]
+#[post("/scheduled-issues/reschedule")]
+async fn reschedule_issue(
+    form: web::Form<RescheduleFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let message = match SendAt::parse(&form.send_at) {
+        Ok(send_at) => {
+            let rescheduled = set_send_at(&pool, form.newsletter_issue_id, send_at)
+                .await
+                .map_err(e500)?;
+            if rescheduled {
+                format!(
+                    "The issue has been rescheduled for {}.",
+                    send_at.as_ref().to_rfc3339()
+                )
+            } else {
+                "The issue is no longer scheduled.".into()
+            }
+        }
+        Err(e) => e,
+    };
+    session.insert_flash(&message).map_err(e500)?;
+
+    Ok(see_other("/admin/scheduled-issues"))
+}
+
+#[tracing::instrument(name = "Cancel a scheduled issue", skip_all, fields(newsletter_issue_id = %form.newsletter_issue_id))]
+#[post("/scheduled-issues/cancel")]
+async fn cancel_issue(
+    form: web::Form<CancelFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let cancelled = cancel_scheduled_issue(&pool, form.newsletter_issue_id)
+        .await
+        .map_err(e500)?;
+
+    let message = if cancelled {
+        "The issue has been cancelled."
+    } else {
+        "The issue is no longer scheduled."
+    };
+    session.insert_flash(message).map_err(e500)?;
+
+    Ok(see_other("/admin/scheduled-issues"))
+}
+
+#[tracing::instrument(name = "Get scheduled issues", skip(pool))]
+async fn get_scheduled_issues(pool: &PgPool) -> Result<Vec<ScheduledIssue>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+        SELECT newsletter_issue_id, title, send_at
+        FROM newsletter_issues
+        WHERE status = $1
+        ORDER BY send_at
+        "#,
+        NewsletterIssueStatus::Scheduled as NewsletterIssueStatus
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the scheduled issues.")?;
+
+    Ok(rows)
+}
+
+// Both return `false` if the issue is not scheduled (anymore): the scheduler
+// holds a lock on the issue while publishing it, so an issue is never
+// changed after its deliveries have been enqueued.
+#[tracing::instrument(name = "Set the send time of a scheduled issue", skip(pool))]
+async fn set_send_at(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    send_at: SendAt,
+) -> Result<bool, anyhow::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET send_at = $3
+        WHERE newsletter_issue_id = $1 AND status = $2
+        "#,
+        newsletter_issue_id,
+        NewsletterIssueStatus::Scheduled as NewsletterIssueStatus,
+        send_at.as_ref()
+    )
+    .execute(pool)
+    .await
+    .context("Failed to reschedule the issue.")?
+    .rows_affected();
+
+    Ok(n_updated_rows == 1)
+}
+
+#[tracing::instrument(name = "Cancel a scheduled issue", skip(pool))]
+async fn cancel_scheduled_issue(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let n_updated_rows = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = $3
+        WHERE newsletter_issue_id = $1 AND status = $2
+        "#,
+        newsletter_issue_id,
+        NewsletterIssueStatus::Scheduled as NewsletterIssueStatus,
+        NewsletterIssueStatus::Cancelled as NewsletterIssueStatus
+    )
+    .execute(pool)
+    .await
+    .context("Failed to cancel the issue.")?
+    .rows_affected();
+
+    Ok(n_updated_rows == 1)
+}
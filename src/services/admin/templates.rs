@@ -0,0 +1,108 @@
+use actix_web::http::header::ContentType;
+use actix_web::{get, web, HttpResponse};
+
+use crate::{
+    startup::ApplicationBaseUrl,
+    templates::{EmailTemplates, MergeFields, RenderedEmail, TEMPLATE_NAMES},
+    utils::e500,
+};
+
+#[derive(serde::Deserialize)]
+struct PreviewQuery {
+    // `html` (the default) or `text`
+    #[serde(default)]
+    format: PreviewFormat,
+}
+
+#[derive(serde::Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum PreviewFormat {
+    #[default]
+    Html,
+    Text,
+}
+
+#[get("/templates")]
+async fn templates_index() -> HttpResponse {
+    let templates_html: String = TEMPLATE_NAMES
+        .iter()
+        .map(|name| {
+            format!(
+                r#"<li>{name}: <a href="/admin/templates/{name}">HTML</a>, <a href="/admin/templates/{name}?format=text">text</a></li>"#
+            )
+        })
+        .collect();
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Email templates</title>
+</head>
+<body>
+    <p>Preview the email templates, rendered with sample data:</p>
+    <ul>{templates_html}</ul>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+        ))
+}
+
+/// Render a template with sample data, the way subscribers would receive it.
+#[get("/templates/{name}")]
+async fn preview_template(
+    name: web::Path<String>,
+    query: web::Query<PreviewQuery>,
+    templates: web::Data<EmailTemplates>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(email) = render_sample(&templates, &name, &base_url.0).transpose() else {
+        return Err(actix_web::error::ErrorNotFound(format!(
+            "There is no template named `{name}`."
+        )));
+    };
+    let email = email.map_err(e500)?;
+
+    let response = match query.0.format {
+        PreviewFormat::Html => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(email.html_content),
+        PreviewFormat::Text => HttpResponse::Ok()
+            .content_type(ContentType::plaintext())
+            .body(email.text_content),
+    };
+
+    Ok(response)
+}
+
+fn render_sample(
+    templates: &EmailTemplates,
+    name: &str,
+    base_url: &str,
+) -> Result<Option<RenderedEmail>, minijinja::Error> {
+    let subscriber_name = "Ursula Le Guin";
+    let email = match name {
+        "confirmation" => templates.confirmation(
+            subscriber_name,
+            &format!("{base_url}/subscriptions/confirm?subscription_token=sample-token"),
+        )?,
+        "already_subscribed" => templates.already_subscribed(subscriber_name)?,
+        "newsletter_issue" => templates.newsletter_issue(
+            "<h1>Sample issue</h1><p>Hi {{ name }}, the content of the issue goes here.</p>",
+            "Sample issue\n\nHi {{ name }}, the content of the issue goes here.",
+            &MergeFields {
+                name: subscriber_name,
+                email: "ursula_le_guin@gmail.com",
+                unsubscribe_url: &format!(
+                    "{base_url}/subscriptions/unsubscribe?token=sample-token"
+                ),
+            },
+        )?,
+        _ => return Ok(None),
+    };
+
+    Ok(Some(email))
+}
@@ -0,0 +1,112 @@
+use actix_web::error::InternalError;
+use actix_web::http::header::ContentType;
+use actix_web::{get, post, web, HttpResponse};
+use secrecy::Secret;
+use sqlx::PgPool;
+
+use super::subscriptions::error_chain_fmt;
+use crate::{
+    authentication::{validate_credentials, AuthError, Credentials},
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+struct FormData {
+    username: String,
+    password: Secret<String>,
+}
+
+#[derive(thiserror::Error)]
+enum LoginError {
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error("Something went wrong.")]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for LoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+#[get("/login")]
+async fn login_form(session: TypedSession) -> HttpResponse {
+    let error_html = match session.take_flash() {
+        Some(message) => format!("<p><i>{}</i></p>", htmlescape::encode_minimal(&message)),
+        None => String::new(),
+    };
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Login</title>
+</head>
+<body>
+    {error_html}
+    <form action="/login" method="post">
+        <label>Username
+            <input type="text" placeholder="Enter Username" name="username">
+        </label>
+        <label>Password
+            <input type="password" placeholder="Enter Password" name="password">
+        </label>
+        <button type="submit">Login</button>
+    </form>
+</body>
+</html>"#,
+        ))
+}
+
+#[tracing::instrument(
+    skip(form, pool, session),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+#[post("/login")]
+async fn login(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> Result<HttpResponse, InternalError<LoginError>> {
+    let credentials = Credentials {
+        username: form.0.username,
+        password: form.0.password,
+    };
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+
+    match validate_credentials(credentials, &pool).await {
+        Ok(user_id) => {
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+            // Rotate the session key on login to prevent session fixation.
+            session.renew();
+            session
+                .insert_user_id(user_id)
+                .map_err(|e| login_redirect(&session, LoginError::UnexpectedError(e.into())))?;
+
+            Ok(see_other("/admin/dashboard"))
+        }
+        Err(e) => {
+            let e = match e {
+                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+                AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
+            };
+
+            Err(login_redirect(&session, e))
+        }
+    }
+}
+
+// Redirect to the login page with a one-time error message.
+// The message is the same for an unknown username and a wrong password.
+fn login_redirect(session: &TypedSession, e: LoginError) -> InternalError<LoginError> {
+    if let Err(insert_error) = session.insert_flash(&e.to_string()) {
+        return InternalError::from_response(e, HttpResponse::from_error(e500(insert_error)));
+    }
+
+    InternalError::from_response(e, see_other("/login"))
+}
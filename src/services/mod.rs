@@ -1,4 +1,8 @@
+pub mod admin;
 pub mod health_check;
+pub mod login;
 pub mod newsletters;
 pub mod subscriptions;
 pub mod subscriptions_confirm;
+pub mod subscriptions_resend;
+pub mod subscriptions_unsubscribe;
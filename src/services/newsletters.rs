@@ -1,6 +1,266 @@
-use actix_web::{post, HttpResponse};
+use actix_web::http::header::{HeaderMap, HeaderValue};
+use actix_web::{http, post, web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use base64::Engine;
+use chrono::Utc;
+use reqwest::StatusCode;
+use secrecy::Secret;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
 
+use super::subscriptions::error_chain_fmt;
+use crate::{
+    authentication::{validate_credentials, AuthError, Credentials},
+    domain::{
+        newsletter_issue::NewsletterIssue, newsletter_issue_status::NewsletterIssueStatus,
+        send_at::SendAt, subscription_status::SubscriptionStatus,
+    },
+    idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+};
+
+#[derive(serde::Deserialize)]
+struct BodyData {
+    title: String,
+    content: Content,
+    // RFC 3339, the issue goes out right away if missing or in the past
+    send_at: Option<String>,
+}
+
+// Either a Markdown source, or both bodies written by hand.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum Content {
+    Markdown { markdown: String },
+    Formatted { html: String, text: String },
+}
+
+impl TryFrom<BodyData> for NewsletterIssue {
+    type Error = String;
+
+    fn try_from(value: BodyData) -> Result<Self, Self::Error> {
+        match value.content {
+            Content::Markdown { markdown } => NewsletterIssue::from_markdown(value.title, markdown),
+            Content::Formatted { html, text } => NewsletterIssue::parse(value.title, html, text),
+        }
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(thiserror::Error)]
+enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PublishError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::AuthError(_) => StatusCode::UNAUTHORIZED,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            Self::AuthError(_) => {
+                let mut response = HttpResponse::new(self.status_code());
+                let header_value = HeaderValue::from_str(r#"Basic realm="publish""#).unwrap();
+                response
+                    .headers_mut()
+                    .insert(http::header::WWW_AUTHENTICATE, header_value);
+                response
+            }
+            _ => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Publish a newsletter issue",
+    skip(body, pool, request),
+    fields(newsletter_title = %body.title, username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
 #[post("/newsletters")]
-async fn publish_newsletter() -> HttpResponse {
-    HttpResponse::Ok().finish()
+async fn publish_newsletter(
+    body: web::Json<BodyData>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    let credentials = basic_authentication(request.headers()).map_err(PublishError::AuthError)?;
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+
+    let user_id = validate_credentials(credentials, &pool)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) => PublishError::AuthError(e.into()),
+            AuthError::UnexpectedError(_) => PublishError::UnexpectedError(e.into()),
+        })?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    let idempotency_key: IdempotencyKey = request
+        .headers()
+        .get("Idempotency-Key")
+        .ok_or_else(|| {
+            PublishError::ValidationError("The 'Idempotency-Key' header is missing.".into())
+        })?
+        .to_str()
+        .map_err(|_| {
+            PublishError::ValidationError("The 'Idempotency-Key' header is not valid UTF8.".into())
+        })?
+        .to_string()
+        .try_into()
+        .map_err(|e: anyhow::Error| PublishError::ValidationError(e.to_string()))?;
+
+    let send_at = body
+        .send_at
+        .as_deref()
+        .map(SendAt::parse)
+        .transpose()
+        .map_err(PublishError::ValidationError)?;
+    let issue = body.0.try_into().map_err(PublishError::ValidationError)?;
+
+    let mut transaction = match try_processing(&pool, &idempotency_key, user_id).await? {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+    };
+
+    enqueue_issue(&mut transaction, &issue, send_at).await?;
+
+    let response = HttpResponse::Accepted().finish();
+    let response = save_response(transaction, &idempotency_key, user_id, response).await?;
+
+    Ok(response)
+}
+
+/// Store a newsletter issue and enqueue one delivery task per confirmed subscriber.
+/// Emails are sent later on by the `issue_delivery_worker`.
+/// An issue to be sent in the future is stored as scheduled instead: its
+/// deliveries are enqueued by the `issue_scheduler` once it is due.
+#[tracing::instrument(
+    name = "Enqueue newsletter issue",
+    skip(transaction, issue),
+    fields(newsletter_title = %issue.title())
+)]
+pub async fn enqueue_issue(
+    transaction: &mut Transaction<'static, Postgres>,
+    issue: &NewsletterIssue,
+    send_at: Option<SendAt>,
+) -> Result<Uuid, anyhow::Error> {
+    let send_at = send_at.filter(SendAt::is_in_the_future);
+    let issue_id = insert_newsletter_issue(transaction, issue, send_at)
+        .await
+        .context("Failed to store newsletter issue details.")?;
+    if send_at.is_none() {
+        enqueue_delivery_tasks(transaction, issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks.")?;
+    }
+
+    Ok(issue_id)
+}
+
+#[tracing::instrument(skip_all)]
+async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue: &NewsletterIssue,
+    send_at: Option<SendAt>,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    let (status, published_at) = match send_at {
+        Some(_) => (NewsletterIssueStatus::Scheduled, None),
+        None => (NewsletterIssueStatus::Published, Some(Utc::now())),
+    };
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            markdown_content,
+            status,
+            send_at,
+            published_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        newsletter_issue_id,
+        issue.title(),
+        issue.text_content(),
+        issue.html_content(),
+        issue.markdown_content(),
+        status as NewsletterIssueStatus,
+        send_at.as_ref().map(AsRef::as_ref),
+        published_at
+    );
+    transaction.execute(query).await?;
+
+    Ok(newsletter_issue_id)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        SELECT $1, email
+        FROM subscriptions
+        WHERE status = $2
+        "#,
+        newsletter_issue_id,
+        SubscriptionStatus::Confirmed as SubscriptionStatus
+    );
+    transaction.execute(query).await?;
+
+    Ok(())
+}
+
+fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    // The header value, if present, must be a valid UTF8 string
+    let header_value = headers
+        .get("Authorization")
+        .context("The 'Authorization' header was missing.")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+
+    // Split into two segments, using ':' as delimiter
+    let mut credentials = decoded_credentials.splitn(2, ':');
+    let username = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A username must be provided in 'Basic' auth."))?
+        .to_string();
+    let password = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A password must be provided in 'Basic' auth."))?
+        .to_string();
+
+    Ok(Credentials {
+        username,
+        password: Secret::new(password),
+    })
 }
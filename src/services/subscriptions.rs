@@ -1,6 +1,6 @@
 use actix_web::{post, web, HttpResponse, ResponseError};
+use anyhow::Context;
 use chrono::Utc;
-use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use reqwest::StatusCode;
 use serde::Deserialize;
 use sqlx::{Executor, PgPool, Postgres, Transaction};
@@ -9,9 +9,11 @@ use uuid::Uuid;
 use crate::{
     domain::{
         new_subscriber::NewSubscriber, subscriber_email::SubscriberEmail,
-        subscriber_name::SubscriberName,
+        subscriber_name::SubscriberName, subscription_status::SubscriptionStatus,
     },
-    email_client::EmailClient,
+    outbox::{enqueue_confirmation, enqueue_email},
+    subscription_tokens::invalidate_tokens,
+    templates::EmailTemplates,
 };
 
 #[derive(Deserialize)]
@@ -36,9 +38,10 @@ enum SubscribeError {
     ValidationError(String),
     PoolError(sqlx::Error),
     InsertSubscriberError(sqlx::Error),
+    FetchSubscriberError(sqlx::Error),
     TransactionCommitError(sqlx::Error),
-    StoreTokenError(StoreTokenError),
-    SendEmailError(reqwest::Error),
+    EmailCooldownError(sqlx::Error),
+    EnqueueEmailError(anyhow::Error),
 }
 
 impl std::fmt::Debug for SubscribeError {
@@ -57,33 +60,31 @@ impl std::fmt::Display for SubscribeError {
             SubscribeError::InsertSubscriberError(_) => {
                 write!(f, "Failed to insert new subscriber in the database.")
             }
+            SubscribeError::FetchSubscriberError(_) => {
+                write!(
+                    f,
+                    "Failed to fetch an existing subscriber from the database."
+                )
+            }
             SubscribeError::TransactionCommitError(_) => {
                 write!(
                     f,
                     "Failed to commit SQL transaction to store a new subscriber."
                 )
             }
-            SubscribeError::StoreTokenError(_) => write!(
-                f,
-                "Failed to store the confirmation token for a new subscriber."
-            ),
-            SubscribeError::SendEmailError(_) => {
-                write!(f, "Failed to send a confirmation email.")
+            SubscribeError::EmailCooldownError(_) => {
+                write!(
+                    f,
+                    "Failed to check or record when the subscriber was last emailed."
+                )
+            }
+            SubscribeError::EnqueueEmailError(_) => {
+                write!(f, "Failed to queue an email for the subscriber.")
             }
         }
     }
 }
 
-impl From<reqwest::Error> for SubscribeError {
-    fn from(e: reqwest::Error) -> Self {
-        Self::SendEmailError(e)
-    }
-}
-impl From<StoreTokenError> for SubscribeError {
-    fn from(e: StoreTokenError) -> Self {
-        Self::StoreTokenError(e)
-    }
-}
 impl From<String> for SubscribeError {
     fn from(e: String) -> Self {
         Self::ValidationError(e)
@@ -96,9 +97,10 @@ impl std::error::Error for SubscribeError {
             SubscribeError::ValidationError(_) => None,
             SubscribeError::PoolError(e) => Some(e),
             SubscribeError::InsertSubscriberError(e) => Some(e),
+            SubscribeError::FetchSubscriberError(e) => Some(e),
             SubscribeError::TransactionCommitError(e) => Some(e),
-            SubscribeError::StoreTokenError(e) => Some(e),
-            SubscribeError::SendEmailError(e) => Some(e),
+            SubscribeError::EmailCooldownError(e) => Some(e),
+            SubscribeError::EnqueueEmailError(e) => Some(e.as_ref()),
         }
     }
 }
@@ -114,7 +116,7 @@ impl ResponseError for SubscribeError {
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool),
+    skip(form, pool, templates),
     fields(
         subscriber_email = %form.email,
         subscriber_name= %form.name
@@ -124,24 +126,66 @@ impl ResponseError for SubscribeError {
 async fn subscribe(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
+    templates: web::Data<EmailTemplates>,
 ) -> Result<HttpResponse, SubscribeError> {
     let mut transaction = pool.begin().await.map_err(SubscribeError::PoolError)?;
 
     let new_subscriber = form.0.try_into()?;
 
-    let subscriber_id: Uuid = insert_subscriber(&new_subscriber, &mut transaction)
+    // Subscribing is idempotent and answers the same way whether or not the
+    // address is already on the list: only the email that follows differs.
+    let subscriber_id = match insert_subscriber(&new_subscriber, &mut transaction)
         .await
-        .map_err(SubscribeError::InsertSubscriberError)?;
+        .map_err(SubscribeError::InsertSubscriberError)?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => {
+            let (subscriber_id, status) =
+                get_existing_subscriber(&mut transaction, &new_subscriber)
+                    .await
+                    .map_err(SubscribeError::FetchSubscriberError)?;
+            // Same cooldown as `resend_confirmation`: posting the form over
+            // and over cannot be used to flood an inbox
+            if was_sent_recently(&mut transaction, subscriber_id)
+                .await
+                .map_err(SubscribeError::EmailCooldownError)?
+            {
+                tracing::info!("An email was sent recently, not sending another one.");
+                return Ok(HttpResponse::Ok().finish());
+            }
+            if status == SubscriptionStatus::Confirmed {
+                enqueue_already_subscribed_email(&mut transaction, &templates, &new_subscriber)
+                    .await
+                    .map_err(SubscribeError::EnqueueEmailError)?;
+                record_email_sent(&mut transaction, subscriber_id)
+                    .await
+                    .map_err(SubscribeError::EmailCooldownError)?;
+                transaction
+                    .commit()
+                    .await
+                    .map_err(SubscribeError::TransactionCommitError)?;
 
-    let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token).await?;
+                return Ok(HttpResponse::Ok().finish());
+            }
+            // Still pending, or coming back after unsubscribing: a fresh link
+            // replaces the previous ones when the email is sent, see
+            // `issue_confirmation_email`, and the subscription has to be
+            // confirmed (again)
+            subscriber_id
+        }
+    };
 
-    // Send a (useless) email to the new subscriber.
-    email_client
-        .send_email(new_subscriber.get_sub_email())
+    enqueue_confirmation_email(
+        &mut transaction,
+        subscriber_id,
+        new_subscriber.get_sub_email(),
+        new_subscriber.get_name(),
+    )
+    .await
+    .map_err(|e| SubscribeError::EnqueueEmailError(e.into()))?;
+    record_email_sent(&mut transaction, subscriber_id)
         .await
-        .map_err(SubscribeError::SendEmailError)?;
+        .map_err(SubscribeError::EmailCooldownError)?;
 
     transaction
         .commit()
@@ -151,6 +195,56 @@ async fn subscribe(
     Ok(HttpResponse::Ok().finish())
 }
 
+/// The email goes out once `transaction` commits, see `outbox`.
+/// Only the subscriber is recorded: the link is issued when the email is
+/// sent, see `subscription_tokens::issue_confirmation_email`.
+#[tracing::instrument(
+    name = "Queue a confirmation email to a new subscriber",
+    skip(transaction, recipient, recipient_name)
+)]
+pub async fn enqueue_confirmation_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    recipient: &SubscriberEmail,
+    recipient_name: &str,
+) -> Result<(), sqlx::Error> {
+    enqueue_confirmation(
+        transaction,
+        subscriber_id,
+        recipient,
+        recipient_name,
+        "Welcome!",
+    )
+    .await
+}
+
+#[tracing::instrument(
+    name = "Queue an already subscribed note to a confirmed subscriber",
+    skip(transaction, templates, new_subscriber)
+)]
+async fn enqueue_already_subscribed_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    templates: &EmailTemplates,
+    new_subscriber: &NewSubscriber,
+) -> Result<(), anyhow::Error> {
+    let email = templates
+        .already_subscribed(new_subscriber.get_name())
+        .context("Failed to render the already subscribed email.")?;
+
+    enqueue_email(
+        transaction,
+        new_subscriber.get_sub_email(),
+        Some(new_subscriber.get_name()),
+        "You are already subscribed",
+        &email.html_content,
+        &email.text_content,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Returns the id of the new subscriber, or `None` if the email is already taken.
 #[tracing::instrument(
     name = "INSERT new subscriber details in the database",
     skip(new_subscriber, transaction)
@@ -158,45 +252,141 @@ async fn subscribe(
 async fn insert_subscriber(
     new_subscriber: &NewSubscriber,
     transaction: &mut Transaction<'_, Postgres>,
-) -> Result<Uuid, sqlx::Error> {
+) -> Result<Option<Uuid>, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
+    // A concurrent request for the same email waits for ours to commit,
+    // then sees the conflict instead of failing on the unique constraint.
     let query = sqlx::query!(
         r#"
     INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-    VALUES ($1, $2, $3, $4, 'pending_confirmation')
+    VALUES ($1, $2, $3, $4, $5)
+    ON CONFLICT (email) DO NOTHING
             "#,
         subscriber_id,
         new_subscriber.get_email(),
         new_subscriber.get_name(),
-        Utc::now()
+        Utc::now(),
+        SubscriptionStatus::PendingConfirmation as SubscriptionStatus
     );
 
-    transaction.execute(query).await?;
+    let n_inserted = transaction.execute(query).await?.rows_affected();
 
-    Ok(subscriber_id)
+    Ok((n_inserted == 1).then_some(subscriber_id))
 }
 
-struct StoreTokenError(sqlx::Error);
+#[tracing::instrument(
+    name = "Fetch an existing subscriber from the database",
+    skip(transaction, new_subscriber)
+)]
+async fn get_existing_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+) -> Result<(Uuid, SubscriptionStatus), sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT id, status AS "status: SubscriptionStatus"
+        FROM subscriptions
+        WHERE email = $1
+        FOR UPDATE
+        "#,
+        new_subscriber.get_email()
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
 
-impl std::fmt::Debug for StoreTokenError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        error_chain_fmt(self, f)
-    }
+    Ok((row.id, row.status))
 }
 
-impl std::fmt::Display for StoreTokenError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Database failure encountered while trying to store subscription token."
-        )
-    }
+// At most one email per address in this window, be it a confirmation link
+// or an "already subscribed" note.
+const EMAIL_COOLDOWN: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Whether the subscriber was emailed during the cooldown.
+/// Callers lock the subscriber row first, so that concurrent requests for the
+/// same address cannot both get through.
+#[tracing::instrument(name = "Check for a recent subscription email", skip(transaction))]
+pub async fn was_sent_recently(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT COALESCE(last_emailed_at > now() - make_interval(secs => $2), false) AS "was_sent_recently!"
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        EMAIL_COOLDOWN.as_secs_f64()
+    )
+    .fetch_one(&mut **transaction)
+    .await
 }
 
-impl std::error::Error for StoreTokenError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        Some(&self.0)
-    }
+/// Start the cooldown, see `was_sent_recently`.
+#[tracing::instrument(name = "Record a subscription email", skip(transaction))]
+pub async fn record_email_sent(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"UPDATE subscriptions SET last_emailed_at = now() WHERE id = $1"#,
+        subscriber_id
+    );
+    transaction.execute(query).await?;
+
+    Ok(())
+}
+
+/// Take the subscriber off the list, as `next`: `Unsubscribed`, `Bounced`
+/// or `Complained`.
+/// The move is checked with `SubscriptionStatus::transition_to`, the row
+/// locked: subscribers who are not on the list anyway (e.g. already
+/// unsubscribed) are left as they are.
+/// The confirmation links sent so far are invalidated: an old link left in
+/// an inbox cannot put them back on the list, see `subscribe`.
+#[tracing::instrument(name = "Remove a subscriber from the list", skip(transaction))]
+pub async fn remove_from_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    next: SubscriptionStatus,
+) -> Result<(), sqlx::Error> {
+    let Some(current) = get_subscriber_status(transaction, subscriber_id).await? else {
+        tracing::info!("The subscriber does not exist anymore.");
+        return Ok(());
+    };
+    let status = match current.transition_to(next) {
+        Ok(status) => status,
+        Err(e) => {
+            tracing::info!(reason = %e, "Nothing to remove from the list.");
+            return Ok(());
+        }
+    };
+
+    let query = sqlx::query!(
+        r#"UPDATE subscriptions SET status = $2, unsubscribed_at = now() WHERE id = $1"#,
+        subscriber_id,
+        status as SubscriptionStatus,
+    );
+    transaction.execute(query).await?;
+
+    invalidate_tokens(transaction, subscriber_id).await
+}
+
+/// The current status of the subscriber, `None` if there is no such
+/// subscriber.
+/// The row is locked until `transaction` ends: check the move with
+/// `SubscriptionStatus::transition_to` before writing the new status.
+#[tracing::instrument(name = "Get subscriber status", skip(transaction))]
+pub async fn get_subscriber_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriptionStatus>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
 }
 
 pub fn error_chain_fmt(
@@ -211,33 +401,3 @@ pub fn error_chain_fmt(
     }
     Ok(())
 }
-
-#[tracing::instrument(
-    name = "Store subscription token in the database",
-    skip(subscription_token, transaction)
-)]
-async fn store_token(
-    transaction: &mut Transaction<'_, Postgres>,
-    subscriber_id: Uuid,
-    subscription_token: &str,
-) -> Result<(), StoreTokenError> {
-    let query = sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES ($1, $2)"#,
-        subscription_token,
-        subscriber_id
-    );
-
-    transaction.execute(query).await.map_err(StoreTokenError)?;
-
-    Ok(())
-}
-
-fn generate_subscription_token() -> String {
-    let mut rng = thread_rng();
-
-    std::iter::repeat_with(|| rng.sample(Alphanumeric))
-        .map(char::from)
-        .take(25)
-        .collect()
-}
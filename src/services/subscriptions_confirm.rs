@@ -1,10 +1,14 @@
 use actix_web::{get, web, HttpResponse, ResponseError};
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use reqwest::StatusCode;
-use sqlx::PgPool;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-use super::subscriptions::error_chain_fmt;
+use super::subscriptions::{error_chain_fmt, get_subscriber_status};
+use crate::domain::{
+    subscription_status::SubscriptionStatus, subscription_token::SubscriptionToken,
+};
 
 #[derive(serde::Deserialize)]
 struct Parameters {
@@ -15,6 +19,10 @@ struct Parameters {
 enum ConfirmationError {
     #[error("There is no subscriber associated with the provided token.")]
     UnknownToken,
+    #[error("The confirmation link has expired.")]
+    ExpiredToken,
+    #[error("The confirmation link has already been used.")]
+    ConsumedToken,
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -29,6 +37,7 @@ impl ResponseError for ConfirmationError {
     fn status_code(&self) -> StatusCode {
         match self {
             Self::UnknownToken => StatusCode::UNAUTHORIZED,
+            Self::ExpiredToken | Self::ConsumedToken => StatusCode::GONE,
             Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -40,41 +49,99 @@ async fn confirm(
     parameters: web::Query<Parameters>,
     pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, ConfirmationError> {
-    let id = get_subscriber_id_from_token(&pool, &parameters.subscription_token)
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+
+    let token_hash = SubscriptionToken::from_link(parameters.0.subscription_token).hash();
+    let token = get_token(&mut transaction, &token_hash)
         .await
         .context("Failed to retrieve the subscriber id associated with the provided token.")?
         .ok_or(ConfirmationError::UnknownToken)?;
+    if token.consumed_at.is_some() {
+        return Err(ConfirmationError::ConsumedToken);
+    }
+    if token.expires_at <= Utc::now() {
+        return Err(ConfirmationError::ExpiredToken);
+    }
 
-    confirm_subscriber(&pool, id)
+    consume_token(&mut transaction, &token_hash)
+        .await
+        .context("Failed to mark the subscription token as consumed.")?;
+    let status = get_subscriber_status(&mut transaction, token.subscriber_id)
+        .await
+        .context("Failed to retrieve the status of the subscriber.")?
+        .context("The subscriber of a subscription token does not exist.")?;
+    // An `Err` means already confirmed, through another link: nothing to do
+    if let Ok(status) = status.transition_to(SubscriptionStatus::Confirmed) {
+        confirm_subscriber(&mut transaction, token.subscriber_id, status)
+            .await
+            .context("Failed to update the subscriber status to `confirmed`.")?;
+    }
+    transaction
+        .commit()
         .await
-        .context("Failed to update the subscriber status to `confirmed`.")?;
+        .context("Failed to commit the SQL transaction to confirm a subscriber.")?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+struct TokenRecord {
+    subscriber_id: Uuid,
+    expires_at: DateTime<Utc>,
+    consumed_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(subscriber_id, transaction)
+)]
+async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    status: SubscriptionStatus,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"UPDATE subscriptions SET status = $2, unsubscribed_at = NULL WHERE id = $1"#,
         subscriber_id,
-    )
-    .execute(pool)
-    .await?;
+        status as SubscriptionStatus,
+    );
+    transaction.execute(query).await?;
 
     Ok(())
 }
 
-#[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
-async fn get_subscriber_id_from_token(
-    pool: &PgPool,
-    subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
-        subscription_token,
+#[tracing::instrument(name = "Get subscription token", skip(token_hash, transaction))]
+async fn get_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    token_hash: &str,
+) -> Result<Option<TokenRecord>, sqlx::Error> {
+    // Lock the row: two concurrent clicks on the same link cannot both consume it
+    sqlx::query_as!(
+        TokenRecord,
+        r#"
+        SELECT subscriber_id, expires_at, consumed_at
+        FROM subscription_tokens
+        WHERE subscription_token_hash = $1
+        FOR UPDATE
+        "#,
+        token_hash,
     )
-    .fetch_optional(pool)
-    .await?;
+    .fetch_optional(&mut **transaction)
+    .await
+}
+
+#[tracing::instrument(name = "Consume subscription token", skip(token_hash, transaction))]
+async fn consume_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    token_hash: &str,
+) -> Result<(), sqlx::Error> {
+    let query = sqlx::query!(
+        r#"UPDATE subscription_tokens SET consumed_at = now() WHERE subscription_token_hash = $1"#,
+        token_hash,
+    );
+    transaction.execute(query).await?;
 
-    Ok(result.map(|r| r.subscriber_id))
+    Ok(())
 }
@@ -0,0 +1,108 @@
+use actix_web::{post, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use reqwest::StatusCode;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::subscriptions::{
+    enqueue_confirmation_email, error_chain_fmt, record_email_sent, was_sent_recently,
+};
+use crate::domain::{subscriber_email::SubscriberEmail, subscription_status::SubscriptionStatus};
+
+#[derive(serde::Deserialize)]
+struct FormData {
+    email: String,
+}
+
+#[derive(thiserror::Error)]
+enum ResendError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ResendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ResendError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+struct PendingSubscriber {
+    id: Uuid,
+    name: String,
+}
+
+/// Send a fresh confirmation link to a pending subscriber, invalidating the
+/// previous ones.
+/// The response is the same whether the address is pending, confirmed,
+/// unknown or rate limited: it cannot be used to probe the list.
+#[tracing::instrument(
+    name = "Resend a confirmation email",
+    skip(form, pool),
+    fields(subscriber_email = %form.email)
+)]
+#[post("/subscriptions/resend-confirmation")]
+async fn resend_confirmation(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ResendError> {
+    let email = SubscriberEmail::parse(form.0.email).map_err(ResendError::ValidationError)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let Some(subscriber) = get_pending_subscriber(&mut transaction, &email)
+        .await
+        .context("Failed to look up the pending subscriber.")?
+    else {
+        return Ok(HttpResponse::Ok().finish());
+    };
+    if was_sent_recently(&mut transaction, subscriber.id)
+        .await
+        .context("Failed to check when the last confirmation email was sent.")?
+    {
+        tracing::info!("An email was sent recently, not sending another one.");
+        return Ok(HttpResponse::Ok().finish());
+    }
+
+    enqueue_confirmation_email(&mut transaction, subscriber.id, &email, &subscriber.name)
+        .await
+        .context("Failed to queue the confirmation email.")?;
+    record_email_sent(&mut transaction, subscriber.id)
+        .await
+        .context("Failed to record when the confirmation email was sent.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction to rotate a subscription token.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Get pending subscriber", skip_all)]
+async fn get_pending_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<PendingSubscriber>, sqlx::Error> {
+    // Lock the row: concurrent requests for the same address are serialised,
+    // so the cooldown cannot be bypassed, see `was_sent_recently`
+    sqlx::query_as!(
+        PendingSubscriber,
+        r#"SELECT id, name FROM subscriptions WHERE email = $1 AND status = $2 FOR UPDATE"#,
+        email.as_ref(),
+        SubscriptionStatus::PendingConfirmation as SubscriptionStatus,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+}
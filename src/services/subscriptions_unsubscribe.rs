@@ -0,0 +1,121 @@
+use actix_web::http::header::ContentType;
+use actix_web::{get, post, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+
+use super::subscriptions::{error_chain_fmt, remove_from_list};
+use crate::{
+    domain::{subscription_status::SubscriptionStatus, unsubscribe_token::UnsubscribeToken},
+    startup::HmacSecret,
+};
+
+#[derive(serde::Deserialize)]
+struct Parameters {
+    token: String,
+}
+
+#[derive(thiserror::Error)]
+enum UnsubscribeError {
+    #[error("{0}")]
+    InvalidToken(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidToken(_) => StatusCode::UNAUTHORIZED,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+// Only asks for a confirmation: link scanners and prefetchers follow `GET`s,
+// they must not be able to unsubscribe anyone.
+#[tracing::instrument(name = "Show the unsubscribe page", skip(parameters, hmac_secret))]
+#[get("/subscriptions/unsubscribe")]
+async fn unsubscribe_form(
+    parameters: web::Query<Parameters>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    UnsubscribeToken::verify(&parameters.token, &hmac_secret.0)
+        .map_err(UnsubscribeError::InvalidToken)?;
+    let token = htmlescape::encode_minimal(&parameters.token);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>Do you want to stop receiving our newsletter?</p>
+    <form action="/subscriptions/unsubscribe?token={token}" method="post">
+        <input hidden type="text" name="List-Unsubscribe" value="One-Click">
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#,
+        )))
+}
+
+// Also the target of the one-click button of mail clients (RFC 8058): they
+// `POST` to the `List-Unsubscribe` URL with a `List-Unsubscribe=One-Click` body,
+// so the token is read from the query string and the body is ignored.
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(parameters, pool, hmac_secret),
+    fields(subscriber_id = tracing::field::Empty)
+)]
+#[post("/subscriptions/unsubscribe")]
+async fn unsubscribe(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let subscriber_id = UnsubscribeToken::verify(&parameters.token, &hmac_secret.0)
+        .map_err(UnsubscribeError::InvalidToken)?;
+    tracing::Span::current().record("subscriber_id", tracing::field::display(&subscriber_id));
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    // Subscribers who are not receiving the newsletter anyway (e.g. already
+    // unsubscribed) get the same answer: there is nothing left to do.
+    remove_from_list(
+        &mut transaction,
+        subscriber_id,
+        SubscriptionStatus::Unsubscribed,
+    )
+    .await
+    .context("Failed to update the subscriber status to `unsubscribed`.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction to unsubscribe a subscriber.")?;
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribed</title>
+</head>
+<body>
+    <p>You have been unsubscribed: you will not receive our newsletter anymore.</p>
+</body>
+</html>"#,
+    ))
+}
@@ -0,0 +1,60 @@
+use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
+use actix_web::dev::Payload;
+use actix_web::{FromRequest, HttpRequest};
+use std::future::{ready, Ready};
+use uuid::Uuid;
+
+// A typed wrapper around `Session`, so that handlers never have to deal
+// with raw string keys.
+pub struct TypedSession(Session);
+
+impl TypedSession {
+    const USER_ID_KEY: &'static str = "user_id";
+    const FLASH_KEY: &'static str = "flash";
+
+    pub fn renew(&self) {
+        self.0.renew();
+    }
+
+    pub fn insert_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::USER_ID_KEY, user_id)
+    }
+
+    pub fn get_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::USER_ID_KEY)
+    }
+
+    // Drop the server-side state and rotate the session key, rather than
+    // purging the session, so that a flash message can still be attached.
+    pub fn log_out(&self) {
+        self.0.clear();
+        self.0.renew();
+    }
+
+    // Flash messages are shown once: reading them removes them from the session.
+    pub fn insert_flash(&self, message: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::FLASH_KEY, message)
+    }
+
+    pub fn take_flash(&self) -> Option<String> {
+        // Only touch the session when there is a message to consume,
+        // so that anonymous visitors do not get a session created for them.
+        let message = self.0.get::<String>(Self::FLASH_KEY).ok().flatten()?;
+        self.0.remove(Self::FLASH_KEY);
+
+        Some(message)
+    }
+}
+
+impl FromRequest for TypedSession {
+    // We return the same error returned by the
+    // implementation of `FromRequest` for `Session`.
+    type Error = <Session as FromRequest>::Error;
+    // No I/O is performed, so we wrap `TypedSession` into `Ready` to get
+    // a `Future` that resolves the first time it's polled.
+    type Future = Ready<Result<TypedSession, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(TypedSession(req.get_session())))
+    }
+}
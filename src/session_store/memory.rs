@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time::Duration;
+use chrono::{DateTime, Utc};
+
+use super::generate_session_key;
+
+type SessionState = HashMap<String, String>;
+type Sessions = HashMap<String, (SessionState, DateTime<Utc>)>;
+
+/// Keeps session states in the memory of the current process.
+/// Sessions are lost on restart and are not shared between instances:
+/// only meant to be used in tests and local development.
+#[derive(Clone, Default)]
+pub struct InMemorySessionStore {
+    sessions: Arc<RwLock<Sessions>>,
+}
+
+impl InMemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn expires_at(ttl: &Duration) -> DateTime<Utc> {
+    Utc::now() + chrono::Duration::seconds(ttl.whole_seconds())
+}
+
+impl SessionStore for InMemorySessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let sessions = self
+            .sessions
+            .read()
+            .map_err(|_| LoadError::Other(anyhow::anyhow!("The session store is poisoned.")))?;
+
+        Ok(sessions
+            .get(session_key.as_ref())
+            .filter(|(_, expires_at)| *expires_at > Utc::now())
+            .map(|(state, _)| state.clone()))
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let session_key = generate_session_key();
+
+        self.sessions
+            .write()
+            .map_err(|_| SaveError::Other(anyhow::anyhow!("The session store is poisoned.")))?
+            .insert(
+                session_key.as_ref().to_string(),
+                (session_state, expires_at(ttl)),
+            );
+
+        Ok(session_key)
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        self.sessions
+            .write()
+            .map_err(|_| UpdateError::Other(anyhow::anyhow!("The session store is poisoned.")))?
+            .insert(
+                session_key.as_ref().to_string(),
+                (session_state, expires_at(ttl)),
+            );
+
+        Ok(session_key)
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> anyhow::Result<()> {
+        let mut sessions = self
+            .sessions
+            .write()
+            .map_err(|_| anyhow::anyhow!("The session store is poisoned."))?;
+
+        if let Some((_, expires)) = sessions.get_mut(session_key.as_ref()) {
+            *expires = expires_at(ttl);
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> anyhow::Result<()> {
+        self.sessions
+            .write()
+            .map_err(|_| anyhow::anyhow!("The session store is poisoned."))?
+            .remove(session_key.as_ref());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claim::{assert_none, assert_ok, assert_some_eq};
+
+    fn state() -> SessionState {
+        HashMap::from([("user_id".to_string(), "\"ursula\"".to_string())])
+    }
+
+    #[actix_web::test]
+    async fn a_saved_session_can_be_loaded() {
+        let store = InMemorySessionStore::new();
+
+        let key = store.save(state(), &Duration::minutes(5)).await.unwrap();
+
+        assert_some_eq!(store.load(&key).await.unwrap(), state());
+    }
+
+    #[actix_web::test]
+    async fn an_expired_session_is_not_loaded() {
+        let store = InMemorySessionStore::new();
+
+        let key = store.save(state(), &Duration::seconds(-1)).await.unwrap();
+
+        assert_none!(store.load(&key).await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn an_updated_session_returns_the_new_state() {
+        let store = InMemorySessionStore::new();
+        let key = store.save(state(), &Duration::minutes(5)).await.unwrap();
+        let new_state = HashMap::from([("flash".to_string(), "\"hi\"".to_string())]);
+
+        let key = store
+            .update(key, new_state.clone(), &Duration::minutes(5))
+            .await
+            .unwrap();
+
+        assert_some_eq!(store.load(&key).await.unwrap(), new_state);
+    }
+
+    #[actix_web::test]
+    async fn a_deleted_session_is_not_loaded() {
+        let store = InMemorySessionStore::new();
+        let key = store.save(state(), &Duration::minutes(5)).await.unwrap();
+
+        assert_ok!(store.delete(&key).await);
+
+        assert_none!(store.load(&key).await.unwrap());
+    }
+}
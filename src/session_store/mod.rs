@@ -0,0 +1,25 @@
+mod memory;
+mod postgres;
+
+use actix_session::storage::SessionKey;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+
+pub use memory::InMemorySessionStore;
+pub use postgres::PgSessionStore;
+
+// Both stores implement `actix_session::storage::SessionStore`, so any of
+// them can be handed to `startup::run` and wired in as session middleware.
+
+// Session keys are stored client-side in the session cookie, 64 alphanumeric
+// characters give us a generous amount of entropy while staying compact.
+fn generate_session_key() -> SessionKey {
+    let mut rng = thread_rng();
+
+    let key: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(64)
+        .collect();
+
+    // A 64 bytes long key is always below the cookie size limit
+    key.try_into().expect("Generated an invalid session key.")
+}
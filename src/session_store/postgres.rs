@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time::Duration;
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+
+use super::generate_session_key;
+
+type SessionState = HashMap<String, String>;
+
+/// Stores session states in the `sessions` table, so that sessions survive
+/// restarts and are shared between all the instances of the application.
+#[derive(Clone)]
+pub struct PgSessionStore {
+    pool: PgPool,
+}
+
+// Expired sessions deleted at once, so that saving a session stays quick
+// even when a large backlog has piled up.
+const PURGE_BATCH_SIZE: i64 = 100;
+
+impl PgSessionStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Delete sessions that expired, a batch at a time.
+    /// Returns the number of sessions deleted.
+    pub async fn purge_expired_sessions(&self) -> Result<u64, anyhow::Error> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM sessions
+            WHERE session_key IN (
+                SELECT session_key FROM sessions
+                WHERE expires_at <= now()
+                LIMIT $1
+            )
+            "#,
+            PURGE_BATCH_SIZE
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to delete the expired sessions from the database.")?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+fn expires_at(ttl: &Duration) -> chrono::DateTime<Utc> {
+    Utc::now() + chrono::Duration::seconds(ttl.whole_seconds())
+}
+
+impl SessionStore for PgSessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let row = sqlx::query!(
+            r#"SELECT state FROM sessions WHERE session_key = $1 AND expires_at > now()"#,
+            session_key.as_ref(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to retrieve the session state from the database.")
+        .map_err(LoadError::Other)?;
+
+        match row {
+            Some(row) => serde_json::from_value(row.state)
+                .context("Failed to deserialize the session state.")
+                .map(Some)
+                .map_err(LoadError::Deserialization),
+            None => Ok(None),
+        }
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let state = serde_json::to_value(&session_state)
+            .context("Failed to serialize the session state.")
+            .map_err(SaveError::Serialization)?;
+        let session_key = generate_session_key();
+
+        sqlx::query!(
+            r#"INSERT INTO sessions (session_key, state, expires_at) VALUES ($1, $2, $3)"#,
+            session_key.as_ref(),
+            state,
+            expires_at(ttl),
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to store the session state in the database.")
+        .map_err(SaveError::Other)?;
+
+        // Nothing else removes expired sessions: a new session is a good time
+        // to clean up. Failing to do so is no reason to fail the request.
+        if let Err(e) = self.purge_expired_sessions().await {
+            tracing::warn!(error.cause_chain = ?e, "Failed to purge expired sessions.");
+        }
+
+        Ok(session_key)
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        let state = serde_json::to_value(&session_state)
+            .context("Failed to serialize the session state.")
+            .map_err(UpdateError::Serialization)?;
+
+        let result = sqlx::query!(
+            r#"UPDATE sessions SET state = $2, expires_at = $3 WHERE session_key = $1"#,
+            session_key.as_ref(),
+            state,
+            expires_at(ttl),
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to update the session state in the database.")
+        .map_err(UpdateError::Other)?;
+
+        // The session has been deleted in the meantime (e.g. it expired),
+        // start a brand-new one rather than failing the request.
+        if result.rows_affected() == 0 {
+            return self.save(session_state, ttl).await.map_err(|e| match e {
+                SaveError::Serialization(e) => UpdateError::Serialization(e),
+                SaveError::Other(e) => UpdateError::Other(e),
+            });
+        }
+
+        Ok(session_key)
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"UPDATE sessions SET expires_at = $2 WHERE session_key = $1"#,
+            session_key.as_ref(),
+            expires_at(ttl),
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to update the session expiration in the database.")?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"DELETE FROM sessions WHERE session_key = $1"#,
+            session_key.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to delete the session from the database.")?;
+
+        Ok(())
+    }
+}
@@ -1,41 +1,103 @@
-use actix_web::{dev::Server, web, App, HttpServer};
+use actix_session::{storage::SessionStore, SessionMiddleware};
+use actix_web::{cookie::Key, dev::Server, web, App, HttpServer};
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use std::net::TcpListener;
 use tracing_actix_web::TracingLogger;
 
 use crate::{
+    authentication::RejectAnonymousUsers,
     config::Settings,
-    email_client::EmailClient,
     services::{
-        health_check::health_check, newsletters::publish_newsletter, subscriptions::subscribe,
+        admin::{
+            dashboard::admin_dashboard,
+            drafts::{
+                create_draft, delete_draft, edit_draft_form, list_drafts, publish_draft,
+                restore_revision, revision_diff, save_draft,
+            },
+            failed_deliveries::{failed_deliveries, retry_failed_delivery},
+            logout::log_out,
+            newsletters::{
+                publish_newsletter as admin_publish_newsletter, publish_newsletter_form,
+            },
+            password::{change_password, change_password_form},
+            scheduled_issues::{cancel_issue, reschedule_issue, scheduled_issues},
+            templates::{preview_template, templates_index},
+        },
+        health_check::health_check,
+        login::{login, login_form},
+        newsletters::publish_newsletter,
+        subscriptions::subscribe,
         subscriptions_confirm::confirm,
+        subscriptions_resend::resend_confirmation,
+        subscriptions_unsubscribe::{unsubscribe, unsubscribe_form},
     },
+    session_store::PgSessionStore,
+    templates::EmailTemplates,
 };
 
-pub async fn build(config: &Settings) -> Result<Server, std::io::Error> {
-    let connection_pool = get_connection_pool(config);
-
-    let sender_email = config
-        .get_email_client()
-        .sender()
-        .expect("Invalid email address.");
-
-    let email_client = EmailClient::new(
-        config.get_email_client().get_base_url().to_string(),
-        sender_email,
-        config.get_email_client().get_secret(),
-        config.get_email_client().get_timeout(),
-    );
-
-    let address = format!("{}:{}", config.get_app_host(), config.get_app_port());
-    let listener = TcpListener::bind(address)?;
-
-    run(
-        listener,
-        connection_pool,
-        email_client,
-        config.get_app_base_url(),
-    )
+pub struct Application {
+    port: u16,
+    server: Server,
+}
+
+impl Application {
+    pub async fn build(config: &Settings) -> Result<Self, anyhow::Error> {
+        let connection_pool = get_connection_pool(config);
+        let session_store = PgSessionStore::new(connection_pool.clone());
+
+        Self::build_with(config, connection_pool, session_store)
+    }
+
+    /// Same as `build`, keeping the sessions in `session_store` rather than
+    /// in Postgres, e.g. an `InMemorySessionStore` in tests.
+    pub async fn build_with_session_store<S>(
+        config: &Settings,
+        session_store: S,
+    ) -> Result<Self, anyhow::Error>
+    where
+        S: SessionStore + Clone + Send + 'static,
+    {
+        Self::build_with(config, get_connection_pool(config), session_store)
+    }
+
+    fn build_with<S>(
+        config: &Settings,
+        connection_pool: PgPool,
+        session_store: S,
+    ) -> Result<Self, anyhow::Error>
+    where
+        S: SessionStore + Clone + Send + 'static,
+    {
+        let address = format!("{}:{}", config.get_app_host(), config.get_app_port());
+        let listener = TcpListener::bind(address)?;
+        // Binding to port 0 lets the OS pick a free port: read back the one we got
+        let port = listener.local_addr()?.port();
+
+        let session_key = session_key(config.get_session_key())?;
+        let email_templates = EmailTemplates::load(config.get_templates_dir())?;
+
+        let server = run(
+            listener,
+            connection_pool,
+            config.get_app_base_url(),
+            config.get_hmac_secret().clone(),
+            session_key,
+            email_templates,
+            session_store,
+        )?;
+
+        Ok(Self { port, server })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    // Only returns when the application is stopped.
+    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+        self.server.await
+    }
 }
 
 pub fn get_connection_pool(config: &Settings) -> PgPool {
@@ -45,34 +107,112 @@ pub fn get_connection_pool(config: &Settings) -> PgPool {
 }
 
 // We need to define a wrapper type in order to retrieve the URL
-// in the `subscribe` handler.
+// in the template preview handler: the emails themselves are rendered by
+// the background workers, which read it from the configuration.
 // Retrieval from the context, in actix-web, is type-based: using
 // a raw `String` would expose us to conflicts.
-pub struct ApplicationBaseUrl(String);
+pub struct ApplicationBaseUrl(pub String);
 
-pub fn run(
+// Signs the unsubscribe links.
+pub struct HmacSecret(pub Secret<String>);
+
+// `Key::derive_from` needs at least this much key material.
+const MIN_SESSION_KEY_LENGTH: usize = 32;
+
+/// The key signing and encrypting the session cookies, derived from the
+/// `app.session_key` setting.
+pub fn session_key(secret: &Secret<String>) -> Result<Key, anyhow::Error> {
+    let secret = secret.expose_secret().as_bytes();
+    if secret.len() < MIN_SESSION_KEY_LENGTH {
+        anyhow::bail!(
+            "The session key must be at least {MIN_SESSION_KEY_LENGTH} bytes long, got {}.",
+            secret.len()
+        );
+    }
+
+    Ok(Key::derive_from(secret))
+}
+
+pub fn run<S>(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
     base_url: &str,
-) -> Result<Server, std::io::Error> {
+    hmac_secret: Secret<String>,
+    session_key: Key,
+    email_templates: EmailTemplates,
+    session_store: S,
+) -> Result<Server, std::io::Error>
+where
+    S: SessionStore + Clone + Send + 'static,
+{
     let db_pool = web::Data::new(db_pool);
-    let email_client = web::Data::new(email_client);
-    let base_url = web::Data::new(base_url.to_string());
+    let base_url = web::Data::new(ApplicationBaseUrl(base_url.to_owned()));
+    let hmac_secret = web::Data::new(HmacSecret(hmac_secret));
+    let email_templates = web::Data::new(email_templates);
 
     let server = HttpServer::new(move || {
         App::new()
+            .wrap(SessionMiddleware::new(
+                session_store.clone(),
+                session_key.clone(),
+            ))
             .wrap(TracingLogger::default())
             .service(health_check)
             .service(subscribe)
             .service(confirm)
+            .service(resend_confirmation)
+            .service(unsubscribe_form)
+            .service(unsubscribe)
             .service(publish_newsletter)
+            .service(login_form)
+            .service(login)
+            .service(
+                web::scope("/admin")
+                    .wrap(RejectAnonymousUsers)
+                    .service(admin_dashboard)
+                    .service(failed_deliveries)
+                    .service(retry_failed_delivery)
+                    .service(scheduled_issues)
+                    .service(reschedule_issue)
+                    .service(cancel_issue)
+                    .service(list_drafts)
+                    .service(create_draft)
+                    .service(edit_draft_form)
+                    .service(save_draft)
+                    .service(revision_diff)
+                    .service(restore_revision)
+                    .service(delete_draft)
+                    .service(publish_draft)
+                    .service(publish_newsletter_form)
+                    .service(admin_publish_newsletter)
+                    .service(templates_index)
+                    .service(preview_template)
+                    .service(change_password_form)
+                    .service(change_password)
+                    .service(log_out),
+            )
             .app_data(db_pool.clone())
-            .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(hmac_secret.clone())
+            .app_data(email_templates.clone())
     })
     .listen(listener)?
     .run();
 
     Ok(server)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_session_keys_are_rejected() {
+        assert!(session_key(&Secret::new("a".repeat(31))).is_err());
+    }
+
+    #[test]
+    fn session_keys_do_not_need_to_be_64_bytes_long() {
+        assert!(session_key(&Secret::new("a".repeat(32))).is_ok());
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::subscription_token::SubscriptionToken,
+    services::subscriptions::error_chain_fmt,
+    templates::{EmailTemplates, RenderedEmail},
+};
+
+/// Issue a fresh confirmation link to `subscriber_id` and render the email
+/// carrying it, as part of `transaction`.
+/// The links sent so far are invalidated and only the hash of the new token
+/// is stored: the raw token only exists in the rendered email.
+#[tracing::instrument(
+    name = "Issue a confirmation email",
+    skip(transaction, templates, recipient_name, base_url, token_ttl)
+)]
+pub async fn issue_confirmation_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    templates: &EmailTemplates,
+    subscriber_id: Uuid,
+    recipient_name: &str,
+    base_url: &str,
+    token_ttl: std::time::Duration,
+) -> Result<RenderedEmail, anyhow::Error> {
+    invalidate_tokens(transaction, subscriber_id)
+        .await
+        .context("Failed to invalidate the previous subscription tokens.")?;
+    let subscription_token = SubscriptionToken::generate();
+    store_token(transaction, subscriber_id, &subscription_token, token_ttl).await?;
+
+    let confirmation_link = format!(
+        "{base_url}/subscriptions/confirm?subscription_token={}",
+        subscription_token.expose()
+    );
+    let email = templates
+        .confirmation(recipient_name, &confirmation_link)
+        .context("Failed to render the confirmation email.")?;
+
+    Ok(email)
+}
+
+pub struct StoreTokenError(sqlx::Error);
+
+impl std::fmt::Debug for StoreTokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl std::fmt::Display for StoreTokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Database failure encountered while trying to store subscription token."
+        )
+    }
+}
+
+impl std::error::Error for StoreTokenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+#[tracing::instrument(
+    name = "Store subscription token in the database",
+    skip(subscription_token, transaction)
+)]
+pub async fn store_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    subscription_token: &SubscriptionToken,
+    ttl: std::time::Duration,
+) -> Result<(), StoreTokenError> {
+    let created_at = Utc::now();
+    // A TTL too large for `chrono` is as good as no expiry at all
+    let expires_at = chrono::Duration::from_std(ttl)
+        .ok()
+        .and_then(|ttl| created_at.checked_add_signed(ttl))
+        .unwrap_or(DateTime::<Utc>::MAX_UTC);
+    let query = sqlx::query!(
+        r#"INSERT INTO subscription_tokens (subscription_token_hash, subscriber_id, created_at, expires_at)
+        VALUES ($1, $2, $3, $4)"#,
+        subscription_token.hash(),
+        subscriber_id,
+        created_at,
+        expires_at
+    );
+
+    transaction.execute(query).await.map_err(StoreTokenError)?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Invalidate subscription tokens", skip(transaction))]
+pub async fn invalidate_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    // Expired rather than deleted: an old link reports itself as expired
+    let query = sqlx::query!(
+        r#"
+        UPDATE subscription_tokens
+        SET expires_at = now()
+        WHERE subscriber_id = $1 AND consumed_at IS NULL AND expires_at > now()
+        "#,
+        subscriber_id
+    );
+    transaction.execute(query).await?;
+
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use tokio::task::JoinHandle;
 use tracing::subscriber::set_global_default;
 use tracing::Subscriber;
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
@@ -34,3 +35,14 @@ pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     // what subscriber should be used to process spans.
     set_global_default(subscriber).expect("Failed to set subscriber");
 }
+
+// Run a CPU-intensive closure on the blocking thread pool, attaching it
+// to the span of the caller so that its logs are correlated with the request.
+pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || current_span.in_scope(f))
+}
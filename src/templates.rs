@@ -0,0 +1,281 @@
+use anyhow::Context;
+use minijinja::{context, AutoEscape, Environment, Output, State, UndefinedBehavior, Value};
+use std::fmt::Write;
+use std::path::Path;
+
+/// The templates an email is rendered from.
+/// Each one is made of a `<name>.html` and a `<name>.txt` file in the
+/// templates directory, see `app.templates_dir` in the configuration.
+pub const TEMPLATE_NAMES: [&str; 3] = ["confirmation", "already_subscribed", "newsletter_issue"];
+
+/// The fields that can be used in the content of an issue, e.g. `{{ name }}`.
+pub const MERGE_FIELDS: [&str; 3] = ["name", "email", "unsubscribe_url"];
+
+/// The values of the merge fields, for one recipient of an issue.
+#[derive(serde::Serialize)]
+pub struct MergeFields<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub unsubscribe_url: &'a str,
+}
+
+pub struct RenderedEmail {
+    pub html_content: String,
+    pub text_content: String,
+}
+
+/// Email bodies, rendered from templates loaded from disk.
+/// Variables are HTML-escaped in `.html` templates (subscriber names
+/// included), and left as they are in `.txt` ones.
+/// Using a variable that was not provided is an error, not a blank.
+pub struct EmailTemplates {
+    env: Environment<'static>,
+}
+
+impl EmailTemplates {
+    /// All templates are read and parsed upfront: a missing file or a syntax
+    /// error stops the application at startup, not at the first email.
+    pub fn load(dir: &Path) -> Result<Self, anyhow::Error> {
+        let mut env = Environment::new();
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+        env.set_formatter(format_value);
+
+        for name in TEMPLATE_NAMES {
+            for extension in ["html", "txt"] {
+                let file_name = format!("{name}.{extension}");
+                let path = dir.join(&file_name);
+                let source = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read the {} template.", path.display()))?;
+                env.add_template_owned(file_name, source)
+                    .with_context(|| format!("Failed to parse the {} template.", path.display()))?;
+            }
+        }
+
+        Ok(Self { env })
+    }
+
+    pub fn confirmation(
+        &self,
+        name: &str,
+        confirmation_link: &str,
+    ) -> Result<RenderedEmail, minijinja::Error> {
+        self.render("confirmation", context! { name, confirmation_link })
+    }
+
+    pub fn already_subscribed(&self, name: &str) -> Result<RenderedEmail, minijinja::Error> {
+        self.render("already_subscribed", context! { name })
+    }
+
+    /// The merge fields of the issue are filled in for the recipient, then
+    /// the issue is wrapped in the `newsletter_issue` template.
+    /// The markup of `html_content` is written by the admins and kept as is,
+    /// only the values of the merge fields are escaped.
+    pub fn newsletter_issue(
+        &self,
+        html_content: &str,
+        text_content: &str,
+        fields: &MergeFields,
+    ) -> Result<RenderedEmail, minijinja::Error> {
+        let html_content = self
+            .env
+            .render_named_str("issue.html", html_content, fields)?;
+        let text_content = self
+            .env
+            .render_named_str("issue.txt", text_content, fields)?;
+        let unsubscribe_url = fields.unsubscribe_url;
+
+        Ok(RenderedEmail {
+            html_content: self.render_one(
+                "newsletter_issue.html",
+                context! { content => Value::from_safe_string(html_content), unsubscribe_url },
+            )?,
+            text_content: self.render_one(
+                "newsletter_issue.txt",
+                context! { content => text_content, unsubscribe_url },
+            )?,
+        })
+    }
+
+    fn render(&self, name: &str, ctx: Value) -> Result<RenderedEmail, minijinja::Error> {
+        Ok(RenderedEmail {
+            html_content: self.render_one(&format!("{name}.html"), ctx.clone())?,
+            text_content: self.render_one(&format!("{name}.txt"), ctx)?,
+        })
+    }
+
+    fn render_one(&self, file_name: &str, ctx: Value) -> Result<String, minijinja::Error> {
+        self.env.get_template(file_name)?.render(ctx)
+    }
+}
+
+/// Check that `content` only uses the known `MERGE_FIELDS`, so that a typo
+/// is caught when publishing rather than sent out as a blank.
+/// Only bare placeholders such as `{{ name }}` are allowed: blocks, filters
+/// and expressions could still fail once rendered for a subscriber.
+pub fn validate_merge_fields(content: &str) -> Result<(), String> {
+    if let Some(tag) = ["{%", "{#"].into_iter().find(|tag| content.contains(tag)) {
+        return Err(format!(
+            "The newsletter content cannot use `{tag}` tags, only merge fields such as {{{{ name }}}}."
+        ));
+    }
+    let mut unknown_fields = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        let after_start = &rest[start + 2..];
+        let Some(end) = after_start.find("}}") else {
+            return Err(
+                "A merge field of the newsletter content is missing its closing `}}`.".into(),
+            );
+        };
+        let placeholder = &after_start[..end];
+        let field = placeholder.trim();
+        // `{{ name.first }}`, `{{ name|upper }}`, `{{- name }}` and the like
+        if field.is_empty() || !field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!(
+                "Only plain merge fields such as {{{{ name }}}} can be used in the newsletter content, not `{{{{{placeholder}}}}}`."
+            ));
+        }
+        if !MERGE_FIELDS.contains(&field) && !unknown_fields.contains(&field) {
+            unknown_fields.push(field);
+        }
+        rest = &after_start[end + 2..];
+    }
+    if unknown_fields.is_empty() {
+        return Ok(());
+    }
+    unknown_fields.sort();
+
+    Err(format!(
+        "Unknown merge fields in the newsletter content: {}. The available fields are {}.",
+        unknown_fields.join(", "),
+        MERGE_FIELDS.join(", ")
+    ))
+}
+
+// The default HTML escaping also escapes `/`, which mangles the links in
+// the raw email body: only escape what could break out of the markup.
+fn format_value(out: &mut Output, state: &State, value: &Value) -> Result<(), minijinja::Error> {
+    match value.as_str() {
+        Some(s) if state.auto_escape() == AutoEscape::Html && !value.is_safe() => {
+            for c in s.chars() {
+                match c {
+                    '&' => out.write_str("&amp;")?,
+                    '<' => out.write_str("&lt;")?,
+                    '>' => out.write_str("&gt;")?,
+                    '"' => out.write_str("&quot;")?,
+                    '\'' => out.write_str("&#x27;")?,
+                    c => out.write_char(c)?,
+                }
+            }
+            Ok(())
+        }
+        _ => minijinja::escape_formatter(out, state, value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_merge_fields, EmailTemplates, MergeFields};
+    use claim::{assert_err, assert_ok};
+    use std::path::Path;
+
+    fn templates() -> EmailTemplates {
+        EmailTemplates::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("templates")).unwrap()
+    }
+
+    fn fields(name: &str) -> MergeFields<'_> {
+        MergeFields {
+            name,
+            email: "ursula@example.com",
+            unsubscribe_url: "http://127.0.0.1/unsubscribe",
+        }
+    }
+
+    #[test]
+    fn subscriber_names_are_escaped_in_html_only() {
+        let email = templates()
+            .confirmation("Tom & Jerry's", "http://127.0.0.1/confirm")
+            .unwrap();
+
+        assert!(email.html_content.contains("Tom &amp; Jerry&#x27;s"));
+        assert!(email.text_content.contains("Tom & Jerry's"));
+    }
+
+    #[test]
+    fn links_are_left_untouched() {
+        let link = "http://127.0.0.1/subscriptions/confirm?subscription_token=abc";
+        let email = templates().confirmation("Ursula", link).unwrap();
+
+        assert!(email.html_content.contains(&format!(r#"href="{link}""#)));
+        assert!(email.text_content.contains(link));
+    }
+
+    #[test]
+    fn issue_content_is_included_as_is() {
+        let email = templates()
+            .newsletter_issue("<p>Hello</p>", "Hello", &fields("Ursula"))
+            .unwrap();
+
+        assert!(email.html_content.starts_with("<p>Hello</p>"));
+        assert!(email.text_content.starts_with("Hello"));
+        assert!(email.text_content.contains("http://127.0.0.1/unsubscribe"));
+    }
+
+    #[test]
+    fn merge_fields_are_escaped_in_the_html_part_only() {
+        let email = templates()
+            .newsletter_issue(
+                "<p>Hi {{ name }}</p>",
+                "Hi {{ name }}, this is for {{ email }}",
+                &fields("Tom & Jerry"),
+            )
+            .unwrap();
+
+        assert!(email.html_content.starts_with("<p>Hi Tom &amp; Jerry</p>"));
+        assert!(email
+            .text_content
+            .starts_with("Hi Tom & Jerry, this is for ursula@example.com"));
+    }
+
+    #[test]
+    fn known_merge_fields_are_valid() {
+        assert_ok!(validate_merge_fields(
+            "Hi {{ name }} ({{ email }}), <a href=\"{{ unsubscribe_url }}\">unsubscribe</a>"
+        ));
+        assert_ok!(validate_merge_fields("No merge fields at all"));
+    }
+
+    #[test]
+    fn unknown_merge_fields_are_rejected() {
+        for content in ["Hi {{ first_name }}", "Hi {{ name.first }}", "Hi {{ name"] {
+            assert_err!(validate_merge_fields(content));
+        }
+    }
+
+    #[test]
+    fn only_bare_merge_fields_are_allowed() {
+        for content in [
+            r#"{% if name < "m" %}Hi{% endif %}"#,
+            "{# a comment #}",
+            "Hi {{ name|upper }}",
+            "Hi {{ name ~ email }}",
+            "Hi {{- name }}",
+            "Hi {{ }}",
+        ] {
+            assert_err!(validate_merge_fields(content));
+        }
+    }
+
+    #[test]
+    fn missing_variables_are_an_error() {
+        let templates = templates();
+        let template = templates.env.get_template("confirmation.html").unwrap();
+
+        assert_err!(template.render(minijinja::context! { name => "Ursula" }));
+    }
+
+    #[test]
+    fn loading_fails_if_a_template_is_missing() {
+        assert!(EmailTemplates::load(Path::new("/does/not/exist")).is_err());
+    }
+}
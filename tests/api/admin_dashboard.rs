@@ -0,0 +1,64 @@
+use crate::setup::{assert_is_redirect_to, spawn_app};
+
+#[actix_web::test]
+async fn you_must_be_logged_in_to_access_the_admin_dashboard() {
+    let app = spawn_app().await;
+
+    let response = app.get_admin_dashboard().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_web::test]
+async fn the_dashboard_shows_the_username_and_subscriber_counts() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES
+            (gen_random_uuid(), 'a@example.com', 'a', now(), 'confirmed'),
+            (gen_random_uuid(), 'b@example.com', 'b', now(), 'confirmed'),
+            (gen_random_uuid(), 'c@example.com', 'c', now(), 'pending_confirmation')"#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let response = app.login_test_user().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+    assert!(html_page.contains("<li>confirmed: 2</li>"));
+    assert!(html_page.contains("<li>pending_confirmation: 1</li>"));
+}
+
+#[actix_web::test]
+async fn every_admin_route_rejects_anonymous_users() {
+    let app = spawn_app().await;
+
+    let response = app.get_publish_newsletter().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_web::test]
+async fn logging_out_locks_the_admin_dashboard() {
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.post_logout().await;
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,134 @@
+use rstest::rstest;
+use uuid::Uuid;
+
+use crate::setup::{assert_is_redirect_to, spawn_app};
+
+#[actix_web::test]
+async fn you_must_be_logged_in_to_see_the_change_password_form() {
+    let app = spawn_app().await;
+
+    let response = app.get_change_password().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_web::test]
+async fn you_must_be_logged_in_to_change_your_password() {
+    let app = spawn_app().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": Uuid::new_v4().to_string(),
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_web::test]
+async fn new_password_fields_must_match() {
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": Uuid::new_v4().to_string(),
+            "new_password_check": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains(
+        "<p><i>You entered two different new passwords - the field values must match.</i></p>"
+    ));
+}
+
+#[actix_web::test]
+async fn current_password_must_be_valid() {
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": Uuid::new_v4().to_string(),
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains("<p><i>The current password is incorrect.</i></p>"));
+}
+
+#[rstest]
+#[case("a".repeat(11), "too short")]
+#[case("a".repeat(129), "too long")]
+#[trace]
+#[actix_web::test]
+async fn new_password_must_respect_the_length_policy(
+    #[case] new_password: String,
+    #[case] test_case: &str,
+) {
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    let html_page = app.get_change_password_html().await;
+    assert!(
+        html_page
+            .contains("<p><i>The new password must be between 12 and 128 characters long.</i></p>"),
+        "The password policy was not enforced when the new password was {test_case}."
+    );
+}
+
+#[actix_web::test]
+async fn changing_password_works() {
+    let app = spawn_app().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app.login_test_user().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains("<p><i>Your password has been changed.</i></p>"));
+
+    let response = app.post_logout().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // The old password no longer works
+    let response = app.login_test_user().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // The new password does
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
@@ -0,0 +1,297 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::setup::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn create_draft(app: &TestApp, title: &str, markdown_content: &str) -> Uuid {
+    let response = app
+        .post_create_draft(&serde_json::json!({
+            "title": title,
+            "markdown_content": markdown_content,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    location
+        .strip_prefix("/admin/drafts/")
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+async fn save_draft(app: &TestApp, draft_id: Uuid, title: &str, markdown_content: &str) {
+    let response = app
+        .post_save_draft(
+            draft_id,
+            &serde_json::json!({
+                "title": title,
+                "markdown_content": markdown_content,
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{draft_id}"));
+}
+
+// The title field of the edit form, as the page escapes it.
+fn title_input_value(title: &str) -> String {
+    format!(r#"value="{}""#, htmlescape::encode_attribute(title))
+}
+
+async fn publish_draft(app: &TestApp, draft_id: Uuid, idempotency_key: &str) -> reqwest::Response {
+    app.post_publish_draft(
+        draft_id,
+        &serde_json::json!({
+            "send_at": "",
+            "idempotency_key": idempotency_key,
+        }),
+    )
+    .await
+}
+
+#[actix_web::test]
+async fn every_save_of_a_draft_is_kept_as_a_revision() {
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    let draft_id = create_draft(&app, "First title", "Hello").await;
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains("<p><i>The draft has been saved.</i></p>"));
+    assert!(html_page.contains(&title_input_value("First title")));
+
+    save_draft(&app, draft_id, "Second title", "Hello\n\nWorld").await;
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains(&title_input_value("Second title")));
+    assert!(html_page.contains(">Hello\n\nWorld</textarea>"));
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/drafts/{draft_id}/revisions/1">Revision 1</a>"#
+    )));
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/drafts/{draft_id}/revisions/2">Revision 2</a>"#
+    )));
+
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains(&format!(
+        r#"<td><a href="/admin/drafts/{draft_id}">Second title</a></td>"#
+    )));
+    assert!(html_page.contains("<td>2</td>"));
+}
+
+#[actix_web::test]
+async fn saving_an_unchanged_draft_does_not_add_a_revision() {
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let draft_id = create_draft(&app, "Title", "Hello").await;
+    app.get_draft_html(draft_id).await;
+
+    save_draft(&app, draft_id, "Title", "Hello").await;
+
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains("<p><i>There are no changes to save.</i></p>"));
+    assert!(!html_page.contains("Revision 2"));
+}
+
+#[actix_web::test]
+async fn revisions_are_shown_as_a_diff() {
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let draft_id = create_draft(&app, "Title", "first line\nsecond line").await;
+    save_draft(&app, draft_id, "Title", "first line\nupdated <line>").await;
+
+    let html_page = app.get_draft_revision_html(draft_id, 2, "").await;
+    assert!(html_page.contains("  first line\n"));
+    assert!(html_page.contains("<del>- second line</del>"));
+    assert!(html_page.contains("<ins>+ updated &lt;line&gt;</ins>"));
+
+    // Against any other revision
+    save_draft(&app, draft_id, "New title", "first line\nsecond line").await;
+    let html_page = app.get_draft_revision_html(draft_id, 3, "?against=1").await;
+    assert!(html_page.contains("<del>- Title</del>"));
+    assert!(html_page.contains("<ins>+ New title</ins>"));
+    assert!(!html_page.contains("<del>- second line</del>"));
+}
+
+#[actix_web::test]
+async fn restoring_a_revision_saves_it_as_a_new_one() {
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let draft_id = create_draft(&app, "Original title", "Original content").await;
+    save_draft(&app, draft_id, "Edited title", "Edited content").await;
+
+    let response = app.post_restore_draft_revision(draft_id, 1).await;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{draft_id}"));
+
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains("<p><i>Revision 1 has been restored as revision 3.</i></p>"));
+    assert!(html_page.contains(&title_input_value("Original title")));
+    assert!(html_page.contains(">Original content</textarea>"));
+    // The edit is still part of the history
+    assert!(html_page.contains("Revision 2"));
+}
+
+#[actix_web::test]
+async fn deleting_a_draft_removes_it_with_its_revisions() {
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let draft_id = create_draft(&app, "Title", "Content").await;
+    save_draft(&app, draft_id, "Title", "Edited content").await;
+
+    let response = app.post_delete_draft(draft_id).await;
+    assert_is_redirect_to(&response, "/admin/drafts");
+
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("<p><i>The draft has been deleted.</i></p>"));
+    assert!(!html_page.contains(&draft_id.to_string()));
+    assert_eq!(app.get_draft(draft_id).await.status().as_u16(), 404);
+    let n_revisions = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM draft_revisions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_revisions, 0);
+}
+
+#[actix_web::test]
+async fn publishing_a_draft_sends_its_latest_revision() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let draft_id = create_draft(&app, "Title", "Old content").await;
+    save_draft(&app, draft_id, "Newsletter title", "Hi {{ name }}, *news*").await;
+
+    let response = publish_draft(&app, draft_id, &Uuid::new_v4().to_string()).await;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{draft_id}"));
+    app.dispatch_all_pending_emails().await;
+
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issue has been accepted - emails will go out shortly.</i></p>"
+    ));
+    assert!(html_page.contains("This draft has been published, it can no longer be edited."));
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Newsletter title");
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("<p>Hi le guin, <em>news</em></p>"));
+    let issue = sqlx::query!("SELECT title, markdown_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.title, "Newsletter title");
+    assert_eq!(
+        issue.markdown_content.as_deref(),
+        Some("Hi {{ name }}, *news*")
+    );
+}
+
+#[actix_web::test]
+async fn published_drafts_can_no_longer_be_edited() {
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let draft_id = create_draft(&app, "Newsletter title", "Content").await;
+    publish_draft(&app, draft_id, &Uuid::new_v4().to_string()).await;
+
+    save_draft(&app, draft_id, "Newsletter title", "Edited content").await;
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page
+        .contains("<p><i>This draft has been published, it can no longer be edited.</i></p>"));
+
+    let response = publish_draft(&app, draft_id, &Uuid::new_v4().to_string()).await;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{draft_id}"));
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains("<p><i>This draft has already been published.</i></p>"));
+
+    let issue = sqlx::query!("SELECT markdown_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.markdown_content.as_deref(), Some("Content"));
+}
+
+#[actix_web::test]
+async fn publishing_a_draft_is_idempotent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let draft_id = create_draft(&app, "Newsletter title", "Content").await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    let response = publish_draft(&app, draft_id, &idempotency_key).await;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{draft_id}"));
+    let response = publish_draft(&app, draft_id, &idempotency_key).await;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{draft_id}"));
+
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issue has been accepted - emails will go out shortly.</i></p>"
+    ));
+    app.dispatch_all_pending_emails().await;
+}
+
+#[actix_web::test]
+async fn drafts_with_unknown_merge_fields_are_not_published() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let draft_id = create_draft(&app, "Newsletter title", "Hi {{ first_name }}").await;
+
+    let response = publish_draft(&app, draft_id, &Uuid::new_v4().to_string()).await;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{draft_id}"));
+
+    let html_page = app.get_draft_html(draft_id).await;
+    assert!(html_page.contains("Unknown merge fields in the newsletter content: first_name."));
+    // Still a draft, it can be fixed
+    assert!(html_page.contains(">Hi {{ first_name }}</textarea>"));
+    app.dispatch_all_pending_emails().await;
+}
+
+#[actix_web::test]
+async fn unknown_drafts_are_not_found() {
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    let response = app.get_draft(Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn you_must_be_logged_in_to_manage_drafts() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_create_draft(&serde_json::json!({
+            "title": "Title",
+            "markdown_content": "Content",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,75 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::setup::{assert_is_redirect_to, spawn_app};
+
+#[actix_web::test]
+async fn you_must_be_logged_in_to_preview_templates() {
+    let app = spawn_app().await;
+
+    let response = app.get_email_template_preview("confirmation").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_web::test]
+async fn templates_are_previewed_with_sample_data() {
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    let html_response = app.get_email_template_preview("confirmation").await;
+    let text_response = app
+        .get_email_template_preview("confirmation?format=text")
+        .await;
+
+    assert_eq!(html_response.status().as_u16(), 200);
+    assert!(html_response.text().await.unwrap().contains(
+        r#"<a href="http://127.0.0.1/subscriptions/confirm?subscription_token=sample-token">"#
+    ));
+    assert_eq!(text_response.status().as_u16(), 200);
+    assert!(text_response
+        .text()
+        .await
+        .unwrap()
+        .contains("Visit http://127.0.0.1/subscriptions/confirm?subscription_token=sample-token"));
+}
+
+#[actix_web::test]
+async fn previewing_an_unknown_template_returns_a_404() {
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    let response = app.get_email_template_preview("not_a_template").await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn subscriber_names_are_escaped_in_html_emails() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.make_post_request("name=Tom%20%26%20Jerry&email=tom_and_jerry%40gmail.com")
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_outbox().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("Hi Tom &amp; Jerry,"));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Hi Tom & Jerry,"));
+}
@@ -0,0 +1,192 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::setup::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn publish_newsletter(app: &TestApp) {
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+async fn count_pending_deliveries(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[actix_web::test]
+async fn transient_failures_are_retried() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // The provider is down for the first attempt only
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // The delivery is rescheduled rather than dropped
+    let task = sqlx::query!(
+        "SELECT n_attempts, next_attempt_at > now() AS is_delayed FROM issue_delivery_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(task.n_attempts, 1);
+    assert_eq!(task.is_delayed, Some(true));
+
+    app.make_pending_deliveries_due().await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(count_pending_deliveries(&app).await, 0);
+}
+
+#[actix_web::test]
+async fn permanent_failures_are_not_retried() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+    app.make_pending_deliveries_due().await;
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(count_pending_deliveries(&app).await, 0);
+    let failed = sqlx::query!("SELECT n_attempts, last_error FROM failed_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failed.n_attempts, 1);
+    assert!(failed.last_error.contains("422"));
+}
+
+#[actix_web::test]
+async fn deliveries_are_given_up_after_too_many_attempts() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(5)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    for _ in 0..10 {
+        app.dispatch_all_pending_emails().await;
+        app.make_pending_deliveries_due().await;
+    }
+
+    assert_eq!(count_pending_deliveries(&app).await, 0);
+    let failed = sqlx::query!("SELECT n_attempts FROM failed_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failed.n_attempts, 5);
+}
+
+#[actix_web::test]
+async fn you_must_be_logged_in_to_see_the_failed_deliveries() {
+    let app = spawn_app().await;
+
+    let response = app.get_failed_deliveries().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_web::test]
+async fn you_must_be_logged_in_to_retry_a_failed_delivery() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_retry_failed_delivery(&serde_json::json!({
+            "newsletter_issue_id": uuid::Uuid::new_v4().to_string(),
+            "subscriber_email": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[actix_web::test]
+async fn failed_deliveries_can_be_retried_by_an_admin() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(400))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let failed =
+        sqlx::query!("SELECT newsletter_issue_id, subscriber_email FROM failed_deliveries")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+
+    // The admin can see the failed delivery
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains(&failed.subscriber_email));
+    assert!(html_page.contains("Newsletter title"));
+
+    // ...and put it back in the queue
+    let response = app
+        .post_retry_failed_delivery(&serde_json::json!({
+            "newsletter_issue_id": failed.newsletter_issue_id.to_string(),
+            "subscriber_email": failed.subscriber_email,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/failed-deliveries");
+
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("<p><i>The delivery has been put back in the queue.</i></p>"));
+    assert!(!html_page.contains(&failed.subscriber_email));
+
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(count_pending_deliveries(&app).await, 0);
+}
@@ -0,0 +1,151 @@
+use secrecy::Secret;
+use zero2prod::authentication::create_user;
+
+use crate::setup::{assert_is_redirect_to, spawn_app, spawn_app_with_postgres_sessions};
+
+#[actix_web::test]
+async fn login_form_is_served() {
+    let app = spawn_app().await;
+
+    let html_page = app.get_login_html().await;
+
+    assert!(html_page.contains(r#"<form action="/login" method="post">"#));
+}
+
+#[actix_web::test]
+async fn an_error_flash_message_is_set_on_failure() {
+    let app = spawn_app().await;
+    let login_body = serde_json::json!({
+        "username": "random-username",
+        "password": "random-password"
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Follow the redirect
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Authentication failed.</i></p>"));
+
+    // Reload the login page: the flash message is gone
+    let html_page = app.get_login_html().await;
+    assert!(!html_page.contains("Authentication failed."));
+}
+
+#[actix_web::test]
+async fn a_wrong_password_shows_the_same_message_as_an_unknown_user() {
+    let app = spawn_app().await;
+    let login_body = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "random-password"
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Authentication failed.</i></p>"));
+}
+
+#[actix_web::test]
+async fn redirect_to_admin_dashboard_after_login_success() {
+    let app = spawn_app().await;
+
+    let response = app.login_test_user().await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[actix_web::test]
+async fn logout_clears_session_state() {
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    let response = app.post_logout().await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Follow the redirect
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>You have successfully logged out.</i></p>"));
+
+    // The session is gone: logging out again is a no-op
+    let response = app.post_logout().await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(!html_page.contains("You have successfully logged out."));
+}
+
+#[actix_web::test]
+async fn created_admins_can_log_in() {
+    let app = spawn_app().await;
+    create_user(
+        "ursula",
+        Secret::new("a-long-enough-password".into()),
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "ursula",
+            "password": "a-long-enough-password",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[actix_web::test]
+async fn no_admin_is_created_by_the_migrations() {
+    let app = spawn_app().await;
+
+    let n_users = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM users"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+
+    // Only the test user
+    assert_eq!(n_users, 1);
+}
+
+#[actix_web::test]
+async fn sessions_are_stored_in_postgres() {
+    let app = spawn_app_with_postgres_sessions().await;
+
+    app.login_test_user().await;
+
+    let n_sessions = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM sessions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_sessions, 1);
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[actix_web::test]
+async fn expired_sessions_are_purged_when_logging_in() {
+    let app = spawn_app_with_postgres_sessions().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO sessions (session_key, state, expires_at)
+        VALUES ('expired-session', '{}', now() - interval '1 minute')
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    app.login_test_user().await;
+
+    let n_expired_sessions =
+        sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM sessions WHERE expires_at <= now()"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count;
+    assert_eq!(n_expired_sessions, 0);
+}
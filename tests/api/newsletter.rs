@@ -1,5 +1,9 @@
-use crate::setup::{spawn_app, TestApp};
+use crate::setup::{
+    assert_is_redirect_to, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+};
 
+use rstest::rstest;
+use uuid::Uuid;
 use wiremock::{
     matchers::{any, method, path},
     Mock, ResponseTemplate,
@@ -16,78 +20,654 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
         .mount(&app.email_server)
         .await;
 
-    // A sketch of the newsletter payload structure.
-    // We might change it later on.
     let newsletter_request_body = serde_json::json!({
-    "test_emails": "Newsletter title",
-    "type": "html"
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
     });
 
-    let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
-        .json(&newsletter_request_body)
-        .send()
-        .await
-        .expect("Failed to execute request.");
+    let response = app.post_newsletters(newsletter_request_body).await;
 
-    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
     // Mock verifies on Drop that we haven't sent the newsletter email
 }
 
 #[actix_web::test]
 async fn newsletters_are_delivered_to_confirmed_subscribers() {
     let app = spawn_app().await;
-    let token = create_unconfirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
 
-    Mock::given(path("/campaigns/9b4079798b/actions/test"))
+    Mock::given(path("/email"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
-        // We assert that no request is fired with our email provider!
-        //.expect(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+
+    let response = app.post_newsletters(newsletter_request_body).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    // The email carries the content of the issue
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Newsletter title");
+    // ...wrapped in the `newsletter_issue` template
+    let text_body = body["TextBody"].as_str().unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(text_body.starts_with("Newsletter body as plain text"));
+    assert!(html_body.starts_with("<p>Newsletter body as HTML</p>"));
+    assert!(text_body.contains("/subscriptions/unsubscribe?token="));
+    assert!(html_body.contains("/subscriptions/unsubscribe?token="));
+    // Mock verifies on Drop that we have sent the newsletter email
+}
+
+#[actix_web::test]
+async fn newsletters_can_be_written_in_markdown() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let markdown = "# Hello\n\nRead [the docs](https://example.com).\n\n<script>alert(1)</script>";
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {"markdown": markdown}
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.starts_with("<h1>Hello</h1>"));
+    assert!(!html_body.contains("<script>"));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Hello\n\nRead the docs (https://example.com)."));
+    // The source is kept next to the rendered bodies
+    let saved = sqlx::query!("SELECT markdown_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.markdown_content.as_deref(), Some(markdown));
+}
+
+#[actix_web::test]
+async fn merge_fields_are_filled_in_for_each_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
         .mount(&app.email_server)
         .await;
 
-    app.confirm_subscriber(&token).await;
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Hi {{ name }}, sent to {{ email }}. Leave: {{ unsubscribe_url }}",
+                "html": r#"<p>Hi {{ name }}, <a href="{{ unsubscribe_url }}">leave</a></p>"#,
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let unsubscribe_link = app.get_unsubscribe_link(email_request);
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    let subscriber_email = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email;
+    assert!(text_body.starts_with(&format!(
+        "Hi le guin, sent to {subscriber_email}. Leave: http://127.0.0.1/subscriptions/unsubscribe?token="
+    )));
+    // Addressed to the subscriber by name
+    let to = body["To"].as_str().unwrap();
+    assert!(to.contains("le guin") && to.contains(&subscriber_email));
+    assert!(html_body.starts_with(
+        r#"<p>Hi le guin, <a href="http://127.0.0.1/subscriptions/unsubscribe?token="#
+    ));
+    assert!(html_body.contains(unsubscribe_link.query().unwrap()));
+}
+
+#[actix_web::test]
+async fn unknown_merge_fields_are_rejected_when_publishing() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
+
+    let api_response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {"markdown": "Hi {{ first_name }}"}
+        }))
+        .await;
+    let form_response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Hi {{ first_name }}",
+            "html_content": "<p>Hi {{ name }}</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    assert_eq!(api_response.status().as_u16(), 400);
+    assert_is_redirect_to(&form_response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("Unknown merge fields in the newsletter content: first_name."));
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
+
+#[actix_web::test]
+async fn template_tags_are_rejected_when_publishing() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Hi there",
+                "html": r#"<p>{% if name < "m" %}Hi{% endif %}</p>"#,
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
+
+#[actix_web::test]
+async fn html_content_is_sanitized() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": r#"<p onclick="steal()">Newsletter body as HTML</p><script>steal()</script>"#,
+            }
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    let saved = sqlx::query!("SELECT html_content, markdown_content FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.html_content, "<p>Newsletter body as HTML</p>");
+    assert_eq!(saved.markdown_content, None);
+}
+
+#[rstest]
+#[case(
+    serde_json::json!({
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }),
+    "missing title"
+)]
+#[case(serde_json::json!({"title": "Newsletter!"}), "missing content")]
+#[case(
+    serde_json::json!({
+        "title": "",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }),
+    "empty title"
+)]
+#[case(
+    serde_json::json!({
+        "title": "Newsletter!",
+        "content": {
+            "text": "",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }),
+    "empty text content"
+)]
+#[case(
+    serde_json::json!({"title": "Newsletter!", "content": {"markdown": " "}}),
+    "empty markdown content"
+)]
+#[trace]
+#[actix_web::test]
+async fn newsletters_returns_400_for_invalid_data(
+    #[case] body: serde_json::Value,
+    #[case] test_case: &str,
+) {
+    let app = spawn_app().await;
+
+    let response = app.post_newsletters(body).await;
+
+    assert_eq!(
+        400,
+        response.status().as_u16(),
+        "The API did not fail with 400 Bad Request when the payload was {test_case}."
+    );
+}
+
+#[actix_web::test]
+async fn publishing_enqueues_one_delivery_per_confirmed_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_unconfirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        // Nothing is sent until the queue is drained
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
 
-    // A sketch of the newsletter payload structure.
-    // We might change it later on.
     let newsletter_request_body = serde_json::json!({
-    "test_emails": "Newsletter title",
-    "type": "html"
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
     });
 
+    let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let issue = sqlx::query!("SELECT newsletter_issue_id, title FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the newsletter issue.");
+    assert_eq!(issue.title, "Newsletter title");
+
+    let tasks = sqlx::query!(
+        "SELECT subscriber_email FROM issue_delivery_queue WHERE newsletter_issue_id = $1",
+        issue.newsletter_issue_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch the delivery tasks.");
+    assert_eq!(tasks.len(), 1);
+}
+
+#[actix_web::test]
+async fn the_queue_can_be_drained_by_concurrent_workers() {
+    let app = spawn_app().await;
+    for _ in 0..5 {
+        create_confirmed_subscriber(&app).await;
+    }
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        // Each subscriber receives the issue exactly once
+        .expect(5)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    tokio::join!(
+        app.dispatch_all_pending_emails(),
+        app.dispatch_all_pending_emails(),
+        app.dispatch_all_pending_emails()
+    );
+}
+
+#[actix_web::test]
+async fn newsletters_published_from_the_admin_form_are_delivered() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(r#"<form action="/admin/newsletters" method="post">"#));
+
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Follow the redirect
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issue has been accepted - emails will go out shortly.</i></p>"
+    ));
+
+    app.dispatch_all_pending_emails().await;
+}
+
+#[actix_web::test]
+async fn admins_can_publish_an_issue_written_in_markdown() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "markdown_content": "Newsletter body in *Markdown*",
+            "text_content": "",
+            "html_content": "",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("<p>Newsletter body in <em>Markdown</em></p>"));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Newsletter body in Markdown"));
+}
+
+#[actix_web::test]
+async fn invalid_admin_form_submissions_show_an_error() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The newsletter title cannot be empty.</i></p>"));
+}
+
+#[actix_web::test]
+async fn requests_missing_an_idempotency_key_are_rejected() {
+    let app = spawn_app().await;
+
     let response = reqwest::Client::new()
-        .post(&format!("{}/newsletters", &app.address))
-        .json(&newsletter_request_body)
+        .post(format!("{}/newsletters", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
         .send()
         .await
         .expect("Failed to execute request.");
 
-    assert_eq!(response.status().as_u16(), 200);
-    // Mock verifies on Drop that we haven't sent the newsletter email
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn newsletter_creation_is_idempotent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_newsletters_with_key(newsletter_request_body.clone(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    // Retry the same request
+    let response = app
+        .post_newsletters_with_key(newsletter_request_body, &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[actix_web::test]
+async fn concurrent_submissions_are_handled_gracefully() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    let response1 =
+        app.post_newsletters_with_key(newsletter_request_body.clone(), &idempotency_key);
+    let response2 = app.post_newsletters_with_key(newsletter_request_body, &idempotency_key);
+    let (response1, response2) = tokio::join!(response1, response2);
+
+    assert_eq!(response1.status(), response2.status());
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email **once**
 }
 
-/// Use the public API of the application under test to create
-/// an unconfirmed subscriber.
-async fn create_unconfirmed_subscriber(app: &TestApp) -> String {
-    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+#[actix_web::test]
+async fn admin_form_submission_is_idempotent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
 
-    let _mock_guard = Mock::given(path("campaigns/9b4079798b/actions/test"))
+    Mock::given(path("/email"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
         .expect(1)
-        .mount_as_scoped(&app.email_server)
+        .mount(&app.email_server)
         .await;
-    app.make_post_request(body)
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issue has been accepted - emails will go out shortly.</i></p>"
+    ));
+
+    // Submit the form **again**
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issue has been accepted - emails will go out shortly.</i></p>"
+    ));
+
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email **once**
+}
+
+#[actix_web::test]
+async fn requests_missing_authorization_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .send()
         .await
-        .error_for_status()
-        .unwrap();
+        .expect("Failed to execute request.");
 
-    let query = sqlx::query!("SELECT subscription_token, subscriber_id FROM subscription_tokens")
-        .fetch_one(&app.db_pool)
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[actix_web::test]
+async fn non_existing_user_is_rejected() {
+    let app = spawn_app().await;
+    // Random credentials
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .basic_auth(username, Some(password))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .send()
         .await
-        .expect("Failed to fetch saved subscription.");
+        .expect("Failed to execute request.");
+
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[actix_web::test]
+async fn invalid_password_is_rejected() {
+    let app = spawn_app().await;
+    let username = &app.test_user.username;
+    // Random password
+    let password = Uuid::new_v4().to_string();
+    assert_ne!(app.test_user.password, password);
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &app.address))
+        .basic_auth(username, Some(password))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
 
-    query.subscription_token.clone()
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        r#"Basic realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
 }
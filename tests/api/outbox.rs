@@ -0,0 +1,175 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::setup::{spawn_app, TestApp};
+
+const BODY: &str = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+async fn outbox_attempts(app: &TestApp) -> Vec<i32> {
+    sqlx::query!("SELECT n_attempts FROM outbox")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.n_attempts)
+        .collect()
+}
+
+#[actix_web::test]
+async fn confirmation_emails_go_through_the_outbox() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.make_post_request(BODY).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    // Nothing is sent while handling the request...
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(outbox_attempts(&app).await, vec![0]);
+    // ...the dispatcher delivers it and clears the outbox
+    app.dispatch_outbox().await;
+    assert!(outbox_attempts(&app).await.is_empty());
+}
+
+#[actix_web::test]
+async fn subscribing_works_while_the_email_provider_is_down() {
+    let app = spawn_app().await;
+    let outage = Mock::given(any())
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let response = app.make_post_request(BODY).await;
+    app.dispatch_outbox().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(outbox_attempts(&app).await, vec![1]);
+    drop(outage);
+
+    // The provider is back: the email is retried once due
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    sqlx::query!("UPDATE outbox SET next_attempt_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_outbox().await;
+    assert!(outbox_attempts(&app).await.is_empty());
+}
+
+#[actix_web::test]
+async fn emails_rejected_by_the_provider_are_dropped() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.make_post_request(BODY).await;
+    app.dispatch_outbox().await;
+
+    assert!(outbox_attempts(&app).await.is_empty());
+}
+
+#[actix_web::test]
+async fn no_email_is_sent_for_a_rolled_back_subscription() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // Fail after the subscriber has been inserted
+    sqlx::query!("ALTER TABLE subscriptions DROP COLUMN last_emailed_at;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.make_post_request(BODY).await;
+    app.dispatch_outbox().await;
+
+    assert_eq!(response.status().as_u16(), 500);
+    assert!(outbox_attempts(&app).await.is_empty());
+}
+
+#[actix_web::test]
+async fn confirmation_links_are_not_stored_in_the_outbox() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.make_post_request(BODY).await;
+
+    let queued = sqlx::query!("SELECT html_content, text_content FROM outbox")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(queued.html_content.is_none());
+    assert!(queued.text_content.is_none());
+    let n_tokens = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tokens, 0);
+
+    // The link is issued when the email is sent
+    app.dispatch_outbox().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[actix_web::test]
+async fn emails_that_cannot_be_prepared_are_retried_later() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.make_post_request(BODY).await;
+    // Issuing the confirmation link fails
+    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscription_token_hash;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    app.dispatch_outbox().await;
+
+    // Rescheduled rather than blocking the outbox
+    assert_eq!(outbox_attempts(&app).await, vec![1]);
+    let n_due =
+        sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM outbox WHERE next_attempt_at <= now()"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count;
+    assert_eq!(n_due, 0);
+}
@@ -0,0 +1,195 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::setup::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn schedule_newsletter(app: &TestApp, send_at: &str) -> Uuid {
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "send_at": send_at,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+// Pretend the send time of all scheduled issues has come.
+async fn fast_forward(app: &TestApp) {
+    sqlx::query!("UPDATE newsletter_issues SET send_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+async fn count_pending_deliveries(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[actix_web::test]
+async fn issues_scheduled_in_the_future_are_only_sent_once_due() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    schedule_newsletter(&app, "2999-01-02T09:00:00+01:00").await;
+    app.publish_due_issues().await;
+    assert_eq!(count_pending_deliveries(&app).await, 0);
+
+    fast_forward(&app).await;
+    app.publish_due_issues().await;
+    assert_eq!(count_pending_deliveries(&app).await, 1);
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that the newsletter went out once
+}
+
+#[actix_web::test]
+async fn issues_scheduled_in_the_past_are_sent_right_away() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    schedule_newsletter(&app, "2000-01-01T00:00:00Z").await;
+
+    assert_eq!(count_pending_deliveries(&app).await, 1);
+}
+
+#[actix_web::test]
+async fn send_times_must_be_rfc3339_with_an_offset() {
+    let app = spawn_app().await;
+
+    for send_at in ["2999-01-02T09:00:00", "next tuesday"] {
+        let response = app
+            .post_newsletters(serde_json::json!({
+                "title": "Newsletter title",
+                "content": {"markdown": "Newsletter body"},
+                "send_at": send_at,
+            }))
+            .await;
+
+        assert_eq!(response.status().as_u16(), 400);
+    }
+}
+
+#[actix_web::test]
+async fn admins_can_schedule_and_reschedule_issues() {
+    let app = spawn_app().await;
+    app.login_test_user().await;
+
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "markdown_content": "Newsletter body",
+            "send_at": "2999-01-02T09:00:00+01:00",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("emails will go out at 2999-01-02T08:00:00+00:00"));
+    let html_page = app.get_scheduled_issues_html().await;
+    assert!(html_page.contains("<td>Newsletter title</td>"));
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    let response = app
+        .post_reschedule_issue(&serde_json::json!({
+            "newsletter_issue_id": issue_id,
+            "send_at": "2999-03-04T10:00:00Z",
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/scheduled-issues");
+    let html_page = app.get_scheduled_issues_html().await;
+    assert!(html_page.contains("The issue has been rescheduled for 2999-03-04T10:00:00+00:00."));
+    assert!(html_page.contains("<td>2999-03-04T10:00:00+00:00</td>"));
+}
+
+#[actix_web::test]
+async fn cancelled_issues_are_never_sent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_test_user().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = schedule_newsletter(&app, "2999-01-02T09:00:00+01:00").await;
+
+    let response = app
+        .post_cancel_issue(&serde_json::json!({"newsletter_issue_id": issue_id}))
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/scheduled-issues");
+    let html_page = app.get_scheduled_issues_html().await;
+    assert!(html_page.contains("The issue has been cancelled."));
+    assert!(!html_page.contains("<td>Newsletter title</td>"));
+    fast_forward(&app).await;
+    app.publish_due_issues().await;
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(count_pending_deliveries(&app).await, 0);
+}
+
+#[actix_web::test]
+async fn published_issues_cannot_be_rescheduled_or_cancelled() {
+    let app = spawn_app().await;
+    app.login_test_user().await;
+    let issue_id = schedule_newsletter(&app, "2999-01-02T09:00:00+01:00").await;
+    fast_forward(&app).await;
+    app.publish_due_issues().await;
+
+    app.post_reschedule_issue(&serde_json::json!({
+        "newsletter_issue_id": issue_id,
+        "send_at": "2999-03-04T10:00:00Z",
+    }))
+    .await;
+    let html_page = app.get_scheduled_issues_html().await;
+    assert!(html_page.contains("The issue is no longer scheduled."));
+    app.post_cancel_issue(&serde_json::json!({"newsletter_issue_id": issue_id}))
+        .await;
+    let html_page = app.get_scheduled_issues_html().await;
+    assert!(html_page.contains("The issue is no longer scheduled."));
+
+    let saved =
+        sqlx::query!("SELECT published_at, send_at < now() AS was_due FROM newsletter_issues")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert!(saved.published_at.is_some());
+    assert_eq!(saved.was_due, Some(true));
+}
+
+#[actix_web::test]
+async fn you_must_be_logged_in_to_manage_scheduled_issues() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_cancel_issue(&serde_json::json!({"newsletter_issue_id": Uuid::new_v4()}))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
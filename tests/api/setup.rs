@@ -1,12 +1,22 @@
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
 use once_cell::sync::Lazy;
-use rand::Rng;
+use secrecy::Secret;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use std::io::{sink, stdout};
+use std::sync::Arc;
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 use zero2prod::config::{get_config, Settings};
-use zero2prod::startup::get_connection_pool;
+use zero2prod::email_client::EmailTransport;
+use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use zero2prod::issue_scheduler::try_publish_due_issue;
+use zero2prod::outbox::try_dispatch_email;
+use zero2prod::session_store::InMemorySessionStore;
+use zero2prod::startup::{get_connection_pool, Application};
 use zero2prod::telemetry::*;
+use zero2prod::templates::EmailTemplates;
 
 static TRACING: Lazy<()> = Lazy::new(|| {
     let subscriber_name = "test";
@@ -26,16 +36,193 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     }
 });
 
+pub struct TestUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String,
+}
+
+impl TestUser {
+    pub fn generate() -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+        }
+    }
+
+    async fn store(&self, pool: &PgPool) {
+        let salt = SaltString::generate(&mut rand::thread_rng());
+        // Match the parameters of the default password hasher
+        let password_hash = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(15000, 2, 1, None).unwrap(),
+        )
+        .hash_password(self.password.as_bytes(), &salt)
+        .unwrap()
+        .to_string();
+
+        sqlx::query!(
+            "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+            self.user_id,
+            self.username,
+            password_hash,
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to store test user.");
+    }
+}
+
+/// Confirmation links embedded in the request to the email API.
+pub struct ConfirmationLinks {
+    pub html: reqwest::Url,
+    pub plain_text: reqwest::Url,
+}
+
 pub struct TestApp {
     pub address: String,
+    pub port: u16,
     pub db_pool: PgPool,
     pub email_server: MockServer,
+    pub test_user: TestUser,
+    pub api_client: reqwest::Client,
+    pub email_client: Arc<dyn EmailTransport>,
+    pub base_url: String,
+    pub subscription_token_ttl: std::time::Duration,
+    pub hmac_secret: Secret<String>,
+    pub email_templates: EmailTemplates,
 }
 
 impl TestApp {
+    /// Extract the confirmation links from a request sent to the email API.
+    pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+
+        let get_link = |s: &str| {
+            let links: Vec<_> = s
+                .split(|c: char| c.is_whitespace() || c == '"')
+                .filter(|word| word.starts_with("http"))
+                .collect();
+            assert_eq!(links.len(), 1);
+
+            let mut confirmation_link = reqwest::Url::parse(links[0]).unwrap();
+            // Make sure we don't call random APIs on the web
+            assert_eq!(confirmation_link.host_str().unwrap(), "127.0.0.1");
+            // The base URL in the configuration has no port: the test app uses a random one
+            confirmation_link.set_port(Some(self.port)).unwrap();
+            confirmation_link
+        };
+
+        ConfirmationLinks {
+            html: get_link(body["HtmlBody"].as_str().unwrap()),
+            plain_text: get_link(body["TextBody"].as_str().unwrap()),
+        }
+    }
+
+    /// Extract the one-click unsubscribe link from the headers of a request
+    /// sent to the email API.
+    pub fn get_unsubscribe_link(&self, email_request: &wiremock::Request) -> reqwest::Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let header = body["Headers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|h| h["Name"] == "List-Unsubscribe")
+            .unwrap();
+        let link = header["Value"]
+            .as_str()
+            .unwrap()
+            .trim_start_matches('<')
+            .trim_end_matches('>');
+
+        let mut unsubscribe_link = reqwest::Url::parse(link).unwrap();
+        assert_eq!(unsubscribe_link.host_str().unwrap(), "127.0.0.1");
+        unsubscribe_link.set_port(Some(self.port)).unwrap();
+        unsubscribe_link
+    }
+
+    pub async fn get_unsubscribe(&self, token: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/subscriptions/unsubscribe", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    // What a mail client does when its one-click button is pressed.
+    pub async fn post_unsubscribe(&self, token: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions/unsubscribe", &self.address))
+            .query(&[("token", token)])
+            .form(&[("List-Unsubscribe", "One-Click")])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Deliver the emails waiting in the outbox (confirmations...).
+    pub async fn dispatch_outbox(&self) {
+        while let ExecutionOutcome::TaskCompleted = try_dispatch_email(
+            &self.db_pool,
+            self.email_client.as_ref(),
+            &self.email_templates,
+            &self.base_url,
+            self.subscription_token_ttl,
+        )
+        .await
+        .unwrap()
+        {}
+    }
+
+    /// Publish the scheduled issues that are due.
+    pub async fn publish_due_issues(&self) {
+        while let ExecutionOutcome::TaskCompleted =
+            try_publish_due_issue(&self.db_pool).await.unwrap()
+        {}
+    }
+
+    /// Deliver the pending newsletter issues.
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                self.email_client.as_ref(),
+                &self.email_templates,
+                &self.base_url,
+                &self.hmac_secret,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    // Pretend the last subscription email went out a while ago.
+    pub async fn skip_email_cooldown(&self) {
+        sqlx::query!(
+            "UPDATE subscriptions SET last_emailed_at = last_emailed_at - interval '1 hour'"
+        )
+        .execute(&self.db_pool)
+        .await
+        .unwrap();
+    }
+
+    // Skip the back-off of the deliveries waiting to be retried.
+    pub async fn make_pending_deliveries_due(&self) {
+        sqlx::query!("UPDATE issue_delivery_queue SET next_attempt_at = now()")
+            .execute(&self.db_pool)
+            .await
+            .expect("Failed to reschedule the pending deliveries.");
+    }
+
     pub async fn make_post_request(&self, body: &str) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body.to_string())
             .send()
@@ -43,22 +230,343 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
-    pub async fn confirm_subscriber(&self, token: &str) -> reqwest::Response {
+    pub async fn post_resend_confirmation(&self, email: &str) -> reqwest::Response {
         reqwest::Client::new()
-            .get(&format!(
-                "{}/subscriptions/confirm?subscription_token={}",
-                &self.address, token
+            .post(format!(
+                "{}/subscriptions/resend-confirmation",
+                &self.address
             ))
+            .form(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
+        self.post_newsletters_with_key(body, &Uuid::new_v4().to_string())
+            .await
+    }
+
+    pub async fn post_newsletters_with_key(
+        &self,
+        body: serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/newsletters", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header("Idempotency-Key", idempotency_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_login_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_dashboard(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/dashboard", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_admin_dashboard_html(&self) -> String {
+        self.get_admin_dashboard().await.text().await.unwrap()
+    }
+
+    pub async fn get_publish_newsletter(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/newsletters", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_publish_newsletter_html(&self) -> String {
+        self.get_publish_newsletter().await.text().await.unwrap()
+    }
+
+    pub async fn post_publish_newsletter<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_email_template_preview(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/templates/{query}", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
     }
+
+    pub async fn get_failed_deliveries(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/failed-deliveries", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_failed_deliveries_html(&self) -> String {
+        self.get_failed_deliveries().await.text().await.unwrap()
+    }
+
+    pub async fn post_retry_failed_delivery<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/failed-deliveries/retry", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_scheduled_issues_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/scheduled-issues", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_reschedule_issue<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/scheduled-issues/reschedule",
+                &self.address
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_cancel_issue<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/scheduled-issues/cancel", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_drafts_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/drafts", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_draft<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/drafts", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_draft(&self, draft_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/drafts/{}", &self.address, draft_id))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_draft_html(&self, draft_id: Uuid) -> String {
+        self.get_draft(draft_id).await.text().await.unwrap()
+    }
+
+    pub async fn post_save_draft<Body>(&self, draft_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/drafts/{}", &self.address, draft_id))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// `query` is appended as is, e.g. `?against=1`.
+    pub async fn get_draft_revision_html(
+        &self,
+        draft_id: Uuid,
+        revision: i32,
+        query: &str,
+    ) -> String {
+        self.api_client
+            .get(format!(
+                "{}/admin/drafts/{}/revisions/{}{}",
+                &self.address, draft_id, revision, query
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_restore_draft_revision(
+        &self,
+        draft_id: Uuid,
+        revision: i32,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/drafts/{}/revisions/{}/restore",
+                &self.address, draft_id, revision
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_delete_draft(&self, draft_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/drafts/{}/delete",
+                &self.address, draft_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_publish_draft<Body>(&self, draft_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/drafts/{}/publish",
+                &self.address, draft_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_change_password(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/password", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_change_password_html(&self) -> String {
+        self.get_change_password().await.text().await.unwrap()
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/password", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_logout(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/logout", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn login_test_user(&self) -> reqwest::Response {
+        self.post_login(&serde_json::json!({
+            "username": &self.test_user.username,
+            "password": &self.test_user.password,
+        }))
+        .await
+    }
 }
 
 /// Spin up an instance of our application
 /// and returns its address (i.e. http://localhost:XXXX)
-#[allow(clippy::let_underscore_future)]
+/// Sessions are kept in memory, see `spawn_app_with_postgres_sessions`.
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with_config(|_| {}).await
+}
+
+/// Same as `spawn_app`, with a chance to tweak the configuration
+/// (e.g. to swap the email provider) before the application is built.
+pub async fn spawn_app_with_config<F>(configure: F) -> TestApp
+where
+    F: FnOnce(&mut Settings),
+{
+    spawn(configure, false).await
+}
+
+/// Same as `spawn_app`, with the sessions stored in Postgres as in
+/// production.
+pub async fn spawn_app_with_postgres_sessions() -> TestApp {
+    spawn(|_| {}, true).await
+}
+
+#[allow(clippy::let_underscore_future)]
+async fn spawn<F>(configure: F, postgres_sessions: bool) -> TestApp
+where
+    F: FnOnce(&mut Settings),
+{
     // The first time `initialize` is invoked the code in `TRACING` is executed.
     // All other invocations will instead skip execution.
     Lazy::force(&TRACING);
@@ -66,30 +574,53 @@ pub async fn spawn_app() -> TestApp {
     let email_server = MockServer::start().await;
 
     // Randomise configuration to ensure test isolation
-    let random_port = rand::thread_rng().gen_range(1000..9000);
     let config = {
         let mut c = get_config().expect("Failed to read config.");
         // Use a different database for each test case
         c.set_db_name(Uuid::new_v4().to_string());
         // Use a random OS port
-        c.set_app_port(random_port);
+        c.set_app_port(0);
         c.set_email_client(email_server.uri());
+        configure(&mut c);
         c
     };
 
     create_db(&config).await;
 
-    let server = zero2prod::startup::build(&config)
-        .await
-        .expect("Failed to bind address");
-    let address = format!("http://127.0.0.1:{}", config.get_app_port());
-    let _ = tokio::spawn(server);
+    let application = if postgres_sessions {
+        Application::build(&config).await
+    } else {
+        Application::build_with_session_store(&config, InMemorySessionStore::new()).await
+    }
+    .expect("Failed to build application.");
+    let port = application.port();
+    let address = format!("http://127.0.0.1:{port}");
+    let _ = tokio::spawn(application.run_until_stopped());
 
-    TestApp {
+    let test_app = TestApp {
         address,
+        port,
         db_pool: get_connection_pool(&config),
         email_server,
-    }
+        test_user: TestUser::generate(),
+        api_client: reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .cookie_store(true)
+            .build()
+            .unwrap(),
+        email_client: config
+            .get_email_client()
+            .transport()
+            .expect("Invalid email client settings."),
+        base_url: config.get_app_base_url().to_owned(),
+        subscription_token_ttl: config.get_subscription_token_ttl(),
+        hmac_secret: config.get_hmac_secret().clone(),
+        email_templates: EmailTemplates::load(config.get_templates_dir())
+            .expect("Failed to load the email templates."),
+    };
+    test_app.test_user.store(&test_app.db_pool).await;
+
+    test_app
 }
 
 // Spin up a brand-new logical database for each integration test run
@@ -111,3 +642,53 @@ async fn create_db(config: &Settings) {
         .await
         .expect("Failed to migrate the database");
 }
+
+/// Use the public API of the application under test to create
+/// an unconfirmed subscriber.
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    // Unique per call, so that several subscribers can be created in one test
+    let email = format!("{}@gmail.com", Uuid::new_v4());
+    let body = format!("name=le%20guin&email={}", email.replace('@', "%40"));
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.make_post_request(&body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_outbox().await;
+
+    // Inspect the requests received by the mock email server
+    // to retrieve the confirmation link
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    app.get_confirmation_links(email_request)
+}
+
+/// Create an unconfirmed subscriber and follow the confirmation
+/// link they received.
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_links = create_unconfirmed_subscriber(app).await;
+
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
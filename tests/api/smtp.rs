@@ -0,0 +1,45 @@
+use crate::setup::spawn_app_with_config;
+use crate::smtp_sink::SmtpSink;
+
+#[actix_web::test]
+async fn confirmation_emails_can_be_relayed_through_smtp() {
+    let sink = SmtpSink::start().await;
+    let app = spawn_app_with_config(|c| c.set_smtp_relay("127.0.0.1".into(), sink.port())).await;
+    let body = "name=Zo%C3%AB%20%C3%85ngstr%C3%B6m&email=zoe%40gmail.com";
+
+    let response = app.make_post_request(body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_outbox().await;
+
+    let messages = sink.received_messages();
+    assert_eq!(messages.len(), 1);
+    let message = &messages[0];
+    let (headers, _) = message.split_once("\r\n\r\n").unwrap();
+
+    // Non-ASCII display names are encoded in headers
+    assert!(headers.is_ascii());
+    assert!(headers.contains("To: =?utf-8?b?"));
+    assert!(headers.contains("<zoe@gmail.com>"));
+    assert!(headers.contains("From: test@gmail.com"));
+    assert!(headers.contains("Subject: Welcome!"));
+    // Both an HTML and a plain text version are sent
+    assert!(message.contains("Content-Type: multipart/alternative;"));
+    assert!(message.contains("Content-Type: text/plain; charset=utf-8"));
+    assert!(message.contains("Content-Type: text/html; charset=utf-8"));
+}
+
+#[actix_web::test]
+async fn smtp_connections_are_reused_across_emails() {
+    let sink = SmtpSink::start().await;
+    let app = spawn_app_with_config(|c| c.set_smtp_relay("127.0.0.1".into(), sink.port())).await;
+
+    for i in 0..3 {
+        let body = format!("name=le%20guin&email=ursula{i}%40gmail.com");
+        let response = app.make_post_request(&body).await;
+        assert_eq!(response.status().as_u16(), 200);
+        app.dispatch_outbox().await;
+    }
+
+    assert_eq!(sink.received_messages().len(), 3);
+    assert_eq!(sink.n_connections(), 1);
+}
@@ -0,0 +1,91 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// A minimal in-process SMTP server: it accepts every message
+/// and keeps its raw content around for assertions.
+pub struct SmtpSink {
+    port: u16,
+    messages: Arc<Mutex<Vec<String>>>,
+    n_connections: Arc<AtomicUsize>,
+}
+
+impl SmtpSink {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind the SMTP sink.");
+        let port = listener.local_addr().unwrap().port();
+        let messages = Arc::new(Mutex::new(Vec::new()));
+
+        let n_connections = Arc::new(AtomicUsize::new(0));
+
+        let sink_messages = messages.clone();
+        let sink_connections = n_connections.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                sink_connections.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(handle_connection(stream, sink_messages.clone()));
+            }
+        });
+
+        Self {
+            port,
+            messages,
+            n_connections,
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn received_messages(&self) -> Vec<String> {
+        self.messages.lock().unwrap().clone()
+    }
+
+    pub fn n_connections(&self) -> usize {
+        self.n_connections.load(Ordering::SeqCst)
+    }
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    messages: Arc<Mutex<Vec<String>>>,
+) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    writer.write_all(b"220 localhost ESMTP sink\r\n").await?;
+    while let Some(line) = lines.next_line().await? {
+        let command = line.to_ascii_uppercase();
+        if command.starts_with("EHLO") {
+            writer
+                .write_all(b"250-localhost\r\n250-8BITMIME\r\n250 SMTPUTF8\r\n")
+                .await?;
+        } else if command.starts_with("DATA") {
+            writer
+                .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                .await?;
+            let mut message = String::new();
+            while let Some(line) = lines.next_line().await? {
+                if line == "." {
+                    break;
+                }
+                message.push_str(&line);
+                message.push_str("\r\n");
+            }
+            messages.lock().unwrap().push(message);
+            writer.write_all(b"250 OK: queued\r\n").await?;
+        } else if command.starts_with("QUIT") {
+            writer.write_all(b"221 Bye\r\n").await?;
+            break;
+        } else {
+            // HELO, MAIL FROM, RCPT TO, RSET, NOOP...
+            writer.write_all(b"250 OK\r\n").await?;
+        }
+    }
+
+    Ok(())
+}
@@ -4,14 +4,17 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::setup::spawn_app;
+use zero2prod::domain::subscription_status::SubscriptionStatus;
+use zero2prod::domain::subscription_token::SubscriptionToken;
+
+use crate::setup::{create_unconfirmed_subscriber, spawn_app};
 
 #[actix_web::test]
 async fn test_subscribe_sends_confirmation_email_for_valid_data() {
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
 
-    Mock::given(path("/campaigns/9b4079798b/actions/test"))
+    Mock::given(path("/email"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
         .expect(1)
@@ -19,6 +22,211 @@ async fn test_subscribe_sends_confirmation_email_for_valid_data() {
         .await;
 
     app.make_post_request(body).await;
+    app.dispatch_outbox().await;
+}
+
+#[actix_web::test]
+async fn subscribe_sends_a_confirmation_email_with_a_link() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.make_post_request(body).await;
+    app.dispatch_outbox().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    // The two links should be identical
+    assert_eq!(confirmation_links.html, confirmation_links.plain_text);
+    assert_eq!(confirmation_links.html.path(), "/subscriptions/confirm");
+    let (name, token) = confirmation_links.html.query_pairs().next().unwrap();
+    assert_eq!(name, "subscription_token");
+    let stored_hash = sqlx::query!("SELECT subscription_token_hash FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .subscription_token_hash;
+    assert_eq!(
+        stored_hash,
+        SubscriptionToken::from_link(token.into_owned()).hash()
+    );
+}
+
+#[actix_web::test]
+async fn subscription_tokens_are_not_stored_in_plain_text() {
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    let (_, token) = confirmation_links.html.query_pairs().next().unwrap();
+
+    let n_matching_rows = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM subscription_tokens WHERE subscription_token_hash = $1"#,
+        token.as_ref()
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+
+    assert_eq!(n_matching_rows, 0);
+}
+
+#[actix_web::test]
+async fn subscribing_twice_while_pending_resends_a_fresh_confirmation_link() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let first_response = app.make_post_request(body).await;
+    app.skip_email_cooldown().await;
+    let second_response = app.make_post_request(body).await;
+    app.dispatch_outbox().await;
+
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 200);
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_link = app.get_confirmation_links(&email_requests[0]).html;
+    let second_link = app.get_confirmation_links(&email_requests[1]).html;
+    assert_ne!(first_link, second_link);
+
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 1);
+
+    // The fresh link confirms the subscription, the previous one is invalidated
+    let response = reqwest::get(first_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 410);
+    reqwest::get(second_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, SubscriptionStatus::Confirmed);
+}
+
+#[actix_web::test]
+async fn subscribing_again_once_confirmed_returns_200_without_a_new_token() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.make_post_request(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_outbox().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    app.skip_email_cooldown().await;
+    let response = app.make_post_request(body).await;
+    app.dispatch_outbox().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let n_tokens = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tokens, 1);
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, SubscriptionStatus::Confirmed);
+
+    // A note rather than another confirmation link
+    let note = &app.email_server.received_requests().await.unwrap()[1];
+    let note: serde_json::Value = serde_json::from_slice(&note.body).unwrap();
+    assert_eq!(note["Subject"], "You are already subscribed");
+    assert!(!note["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("/subscriptions/confirm"));
+}
+
+#[actix_web::test]
+async fn subscribing_twice_in_a_row_sends_a_single_email() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let first_response = app.make_post_request(body).await;
+    let second_response = app.make_post_request(body).await;
+    app.dispatch_outbox().await;
+
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 200);
+    let n_tokens = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tokens, 1);
+}
+
+#[actix_web::test]
+async fn already_subscribed_notes_are_rate_limited_as_well() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        // The confirmation link, then a single note
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.make_post_request(body).await;
+    app.dispatch_outbox().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.skip_email_cooldown().await;
+
+    let first_response = app.make_post_request(body).await;
+    let second_response = app.make_post_request(body).await;
+    app.dispatch_outbox().await;
+
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 200);
 }
 
 #[actix_web::test]
@@ -26,7 +234,7 @@ async fn test_subscriber_code_200() {
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
 
-    Mock::given(path("/campaigns/9b4079798b/actions/test"))
+    Mock::given(path("/email"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
         .mount(&app.email_server)
@@ -41,7 +249,7 @@ async fn test_subscribe_does_persist_subscriber() {
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
 
-    Mock::given(path("/campaigns/9b4079798b/actions/test"))
+    Mock::given(path("/email"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
         .mount(&app.email_server)
@@ -49,14 +257,16 @@ async fn test_subscribe_does_persist_subscriber() {
 
     app.make_post_request(body).await;
 
-    let query = sqlx::query!("SELECT email, name, status FROM subscriptions")
-        .fetch_one(&app.db_pool)
-        .await
-        .expect("Failed to fetch saved subscription.");
+    let query = sqlx::query!(
+        r#"SELECT email, name, status AS "status: SubscriptionStatus" FROM subscriptions"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription.");
 
     assert_eq!(query.email, "ursula_le_guin@gmail.com");
     assert_eq!(query.name, "le guin");
-    assert_eq!(query.status, "pending_confirmation");
+    assert_eq!(query.status, SubscriptionStatus::PendingConfirmation);
 }
 
 #[rstest]
@@ -101,7 +311,7 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
     // Sabotage the database
-    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscription_token;",)
+    sqlx::query!("ALTER TABLE subscriptions DROP COLUMN last_emailed_at;")
         .execute(&app.db_pool)
         .await
         .unwrap();
@@ -3,7 +3,9 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::setup::spawn_app;
+use zero2prod::domain::subscription_status::SubscriptionStatus;
+
+use crate::setup::{create_unconfirmed_subscriber, spawn_app};
 
 #[actix_web::test]
 async fn confirmations_without_token_are_rejected_with_400() {
@@ -16,36 +18,132 @@ async fn confirmations_without_token_are_rejected_with_400() {
 }
 
 #[actix_web::test]
-async fn test_confirmation_persist() {
+async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
 
-    Mock::given(path("/campaigns/9b4079798b/actions/test"))
+    Mock::given(path("/email"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
         .mount(&app.email_server)
         .await;
 
     app.make_post_request(body).await;
+    app.dispatch_outbox().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
 
-    let query = sqlx::query!("SELECT email, name, status FROM subscriptions")
-        .fetch_one(&app.db_pool)
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.make_post_request(body).await;
+    app.dispatch_outbox().await;
+
+    let query = sqlx::query!(
+        r#"SELECT email, name, status AS "status: SubscriptionStatus" FROM subscriptions"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription.");
+    assert_eq!(query.status, SubscriptionStatus::PendingConfirmation);
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    reqwest::get(confirmation_links.html)
         .await
-        .expect("Failed to fetch saved subscription.");
+        .unwrap()
+        .error_for_status()
+        .unwrap();
 
-    assert_eq!(query.status, "pending_confirmation");
+    let query = sqlx::query!(
+        r#"SELECT email, name, status AS "status: SubscriptionStatus" FROM subscriptions"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription.");
 
-    let query = sqlx::query!("SELECT subscription_token, subscriber_id FROM subscription_tokens")
-        .fetch_one(&app.db_pool)
+    assert_eq!(query.email, "ursula_le_guin@gmail.com");
+    assert_eq!(query.name, "le guin");
+    assert_eq!(query.status, SubscriptionStatus::Confirmed);
+}
+
+#[actix_web::test]
+async fn confirmations_with_an_unknown_token_are_rejected_with_401() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/confirm?subscription_token=unknowntoken",
+        &app.address
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn tokens_expire_after_the_configured_ttl() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+
+    let token = sqlx::query!(
+        r#"SELECT EXTRACT(EPOCH FROM expires_at - created_at)::BIGINT AS "ttl_secs!" FROM subscription_tokens"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    assert_eq!(token.ttl_secs, 86400);
+}
+
+#[actix_web::test]
+async fn expired_confirmation_links_are_rejected_with_410() {
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 second'")
+        .execute(&app.db_pool)
         .await
-        .expect("Failed to fetch saved subscription.");
+        .unwrap();
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
 
-    app.confirm_subscriber(&query.subscription_token).await;
+    assert_eq!(response.status().as_u16(), 410);
+    let subscriber =
+        sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(subscriber.status, SubscriptionStatus::PendingConfirmation);
+}
 
-    let q = sqlx::query!("SELECT email, name, status FROM subscriptions")
+#[actix_web::test]
+async fn confirmation_links_can_only_be_used_once() {
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    let response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let token = sqlx::query!("SELECT consumed_at FROM subscription_tokens")
         .fetch_one(&app.db_pool)
         .await
-        .expect("Failed to fetch saved subscription.");
+        .unwrap();
+    assert!(token.consumed_at.is_some());
 
-    assert_eq!(q.status, "confirmed");
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 410);
 }
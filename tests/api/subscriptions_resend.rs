@@ -0,0 +1,113 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::setup::{
+    create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app, TestApp,
+};
+
+async fn only_subscriber_email(app: &TestApp) -> String {
+    sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email
+}
+
+#[actix_web::test]
+async fn pending_subscribers_receive_a_fresh_confirmation_link() {
+    let app = spawn_app().await;
+    let old_links = create_unconfirmed_subscriber(&app).await;
+    app.skip_email_cooldown().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_resend_confirmation(&only_subscriber_email(&app).await)
+        .await;
+    app.dispatch_outbox().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let new_links = app.get_confirmation_links(email_request);
+    assert_ne!(new_links.html, old_links.html);
+    // The previous link is invalidated...
+    let response = reqwest::get(old_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 410);
+    // ...the new one works
+    let response = reqwest::get(new_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn resending_is_rate_limited_per_address() {
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    let email = only_subscriber_email(&app).await;
+    app.skip_email_cooldown().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        // Only the first of the two requests goes through
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let first_response = app.post_resend_confirmation(&email).await;
+    let second_response = app.post_resend_confirmation(&email).await;
+    app.dispatch_outbox().await;
+
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 200);
+}
+
+#[actix_web::test]
+async fn unknown_and_confirmed_addresses_get_the_same_response_and_no_email() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let confirmed_email = only_subscriber_email(&app).await;
+    create_unconfirmed_subscriber(&app).await;
+    app.skip_email_cooldown().await;
+    let pending_email = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE email <> $1",
+        confirmed_email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .email;
+    let _mock_guard = Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let pending_response = app.post_resend_confirmation(&pending_email).await;
+    let pending_response = (
+        pending_response.status(),
+        pending_response.text().await.unwrap(),
+    );
+    for email in [confirmed_email.as_str(), "not_on_the_list@example.com"] {
+        let response = app.post_resend_confirmation(email).await;
+        let response = (response.status(), response.text().await.unwrap());
+
+        assert_eq!(response, pending_response);
+    }
+    app.dispatch_outbox().await;
+}
+
+#[actix_web::test]
+async fn invalid_emails_are_rejected_with_a_400() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_resend_confirmation("definitely-not-an-email")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
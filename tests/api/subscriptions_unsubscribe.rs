@@ -0,0 +1,313 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::domain::subscription_status::SubscriptionStatus;
+use zero2prod::domain::subscription_token::SubscriptionToken;
+use zero2prod::domain::unsubscribe_token::UnsubscribeToken;
+
+use crate::setup::{create_confirmed_subscriber, spawn_app, TestApp};
+
+async fn publish_newsletter(app: &TestApp) {
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+}
+
+async fn only_subscriber_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+#[actix_web::test]
+async fn newsletters_carry_one_click_unsubscribe_headers() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["Headers"].as_array().unwrap().iter().any(|h| h["Name"]
+        == "List-Unsubscribe-Post"
+        && h["Value"] == "List-Unsubscribe=One-Click"));
+    let unsubscribe_link = app.get_unsubscribe_link(email_request);
+    assert_eq!(unsubscribe_link.path(), "/subscriptions/unsubscribe");
+}
+
+#[actix_web::test]
+async fn the_unsubscribe_link_of_a_newsletter_works_in_one_click() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter(&app).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let unsubscribe_link = app.get_unsubscribe_link(email_request);
+
+    let response = reqwest::Client::new()
+        .post(unsubscribe_link)
+        .form(&[("List-Unsubscribe", "One-Click")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!(
+        r#"SELECT status AS "status: SubscriptionStatus", unsubscribed_at FROM subscriptions"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.status, SubscriptionStatus::Unsubscribed);
+    assert!(saved.unsubscribed_at.is_some());
+}
+
+#[actix_web::test]
+async fn the_unsubscribe_page_asks_for_a_confirmation_without_unsubscribing() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = UnsubscribeToken::sign(only_subscriber_id(&app).await, &app.hmac_secret);
+
+    let response = app.get_unsubscribe(token.as_ref()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&format!(
+        r#"<form action="/subscriptions/unsubscribe?token={}" method="post">"#,
+        token.as_ref()
+    )));
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, SubscriptionStatus::Confirmed);
+}
+
+#[actix_web::test]
+async fn unsubscribed_subscribers_do_not_receive_newsletters() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = UnsubscribeToken::sign(only_subscriber_id(&app).await, &app.hmac_secret);
+    app.post_unsubscribe(token.as_ref())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    publish_newsletter(&app).await;
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[actix_web::test]
+async fn deliveries_queued_before_unsubscribing_are_dropped() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = UnsubscribeToken::sign(only_subscriber_id(&app).await, &app.hmac_secret);
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    app.post_unsubscribe(token.as_ref())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let n_tasks = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tasks, 0);
+}
+
+#[actix_web::test]
+async fn unsubscribing_twice_keeps_the_first_timestamp() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = UnsubscribeToken::sign(only_subscriber_id(&app).await, &app.hmac_secret);
+    let unsubscribed_at = || async {
+        sqlx::query!("SELECT unsubscribed_at FROM subscriptions")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .unsubscribed_at
+    };
+
+    let first_response = app.post_unsubscribe(token.as_ref()).await;
+    let first_unsubscribed_at = unsubscribed_at().await;
+    let second_response = app.post_unsubscribe(token.as_ref()).await;
+
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 200);
+    assert!(first_unsubscribed_at.is_some());
+    assert_eq!(unsubscribed_at().await, first_unsubscribed_at);
+}
+
+#[actix_web::test]
+async fn tokens_that_are_not_signed_by_the_application_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let forged = UnsubscribeToken::sign(
+        only_subscriber_id(&app).await,
+        &secrecy::Secret::new("not-the-application-secret".into()),
+    );
+
+    let get_response = app.get_unsubscribe(forged.as_ref()).await;
+    let post_response = app.post_unsubscribe(forged.as_ref()).await;
+
+    assert_eq!(get_response.status().as_u16(), 401);
+    assert_eq!(post_response.status().as_u16(), 401);
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, SubscriptionStatus::Confirmed);
+}
+
+#[actix_web::test]
+async fn requests_without_a_token_are_rejected_with_a_400() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions/unsubscribe", app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[actix_web::test]
+async fn subscribing_again_after_unsubscribing_requires_a_new_confirmation() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.make_post_request(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_outbox().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let token = UnsubscribeToken::sign(only_subscriber_id(&app).await, &app.hmac_secret);
+    app.post_unsubscribe(token.as_ref())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    app.skip_email_cooldown().await;
+    app.make_post_request(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_outbox().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, SubscriptionStatus::Unsubscribed);
+
+    reqwest::get(app.get_confirmation_links(email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let saved = sqlx::query!(
+        r#"SELECT status AS "status: SubscriptionStatus", unsubscribed_at FROM subscriptions"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.status, SubscriptionStatus::Confirmed);
+    assert!(saved.unsubscribed_at.is_none());
+}
+
+#[actix_web::test]
+async fn old_confirmation_links_cannot_undo_an_unsubscription() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = only_subscriber_id(&app).await;
+    // A link from an earlier email, never clicked and not expired yet
+    let old_token = SubscriptionToken::generate();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token_hash, subscriber_id, created_at, expires_at)
+        VALUES ($1, $2, now(), now() + interval '1 day')
+        "#,
+        old_token.hash(),
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    let token = UnsubscribeToken::sign(subscriber_id, &app.hmac_secret);
+    app.post_unsubscribe(token.as_ref())
+        .await
+        .error_for_status()
+        .unwrap();
+    let response = reqwest::get(format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        app.address,
+        old_token.expose()
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 410);
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, SubscriptionStatus::Unsubscribed);
+}